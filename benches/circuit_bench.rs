@@ -1,4 +1,7 @@
-use std::{cell::RefCell, sync::Arc, time::Duration};
+// The builder API is built around [Arc<RefCell<_>>]
+#![allow(clippy::arc_with_non_send_sync)]
+
+use std::{cell::RefCell, sync::Arc};
 
 use criterion::{criterion_group, criterion_main, Criterion};
 use rand::{RngCore, SeedableRng};
@@ -50,7 +53,8 @@ fn sram_benches(c: &mut Criterion) {
     let mut circuit = Circuit::new();
     let sram = Sram::new::<{ 1 << 12 }>(&mut circuit);
     // let sram = Sram::new_full_2d(&mut circuit);
-    println!("{}", circuit.num_nodes());
+    println!("nodes: {}", circuit.num_nodes());
+    println!("memory: {} bytes", circuit.memory_bytes());
 
     c.bench_function("131K SRAM store", |b| {
         let mut rng = rand::rngs::StdRng::from_entropy();
@@ -66,7 +70,7 @@ fn sram_benches(c: &mut Criterion) {
     c.bench_function("131K SRAM load", |b| {
         let mut rng = rand::rngs::StdRng::from_entropy();
         b.iter_batched(
-            move || (rng.next_u32() as u16 >> 4),
+            move || rng.next_u32() as u16 >> 4,
             |address| sram.get(&mut circuit, address),
             criterion::BatchSize::SmallInput,
         )
@@ -6,7 +6,7 @@ use bevy::{
     utils::{Duration, HashMap},
 };
 use bevy_rapier3d::prelude::*;
-use digisim::{circuit_sim::CircuitSim, Circuit};
+use digisim::{circuit_sim::CircuitSim, components::wire::Wire, Circuit};
 
 #[derive(Default)]
 struct CameraState {
@@ -65,13 +65,49 @@ impl CameraState {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 struct Coord {
     x: i32,
     y: i32,
     z: i32,
 }
 
+/// Bits per axis when packing a [Coord] into an address. Each axis is
+/// stored as two's complement, so coordinates must lie in -512..512.
+const ADDRESS_AXIS_BITS: u32 = 10;
+
+/// Bits in a whole block address, which is how wide [Game::address] is.
+const ADDRESS_BITS: usize = 3 * ADDRESS_AXIS_BITS as usize;
+
+/// Packs block coordinates into a memory address, with x in the lowest
+/// [ADDRESS_AXIS_BITS] bits, then y, then z. Every block gets its own
+/// address, so decoding slices of it selects a block.
+fn coord_to_address(coord: Coord) -> u32 {
+    let limit = 1 << (ADDRESS_AXIS_BITS - 1);
+    let mask = (1 << ADDRESS_AXIS_BITS) - 1;
+    [coord.x, coord.y, coord.z]
+        .into_iter()
+        .enumerate()
+        .fold(0, |address, (axis, val)| {
+            assert!(
+                (-limit..limit).contains(&val),
+                "{coord:?} can't be addressed"
+            );
+            address | ((val as u32 & mask) << (axis as u32 * ADDRESS_AXIS_BITS))
+        })
+}
+
+/// Inverse of [coord_to_address].
+fn address_to_coord(address: u32) -> Coord {
+    let shift = u32::BITS - ADDRESS_AXIS_BITS;
+    let axis = |axis: u32| ((address >> (axis * ADDRESS_AXIS_BITS)) << shift) as i32 >> shift;
+    Coord {
+        x: axis(0),
+        y: axis(1),
+        z: axis(2),
+    }
+}
+
 enum Side {
     Front,
     Back,
@@ -112,11 +148,26 @@ impl CircuitNode {
     fn connect(&self, side: Side, other: &Self, circuit: &mut Circuit) {}
 }
 
+/// Sets an input of the circuit before the next [Game::tick]. Input sources
+/// like levers, the keyboard or scripts send these instead of setting inputs
+/// directly.
+struct CircuitInputEvent {
+    node: digisim::NodeId,
+    value: bool,
+}
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+struct ApplyInputs;
+
 struct Game {
     circuit: Circuit,
+    /// Address of the last placed block, as circuit inputs that memories
+    /// can decode.
+    address: Wire<ADDRESS_BITS>,
     block_mesh: Handle<Mesh>,
     blocks: HashMap<Coord, Entity>,
     last_tick: Duration,
+    paused: bool,
 }
 
 impl Game {
@@ -126,11 +177,15 @@ impl Game {
         mut meshes: ResMut<Assets<Mesh>>,
         time: Res<Time>,
     ) {
+        let mut circuit = Circuit::new();
+        let address = Wire::new(&mut circuit);
         let game = Game {
-            circuit: Circuit::new(),
+            circuit,
+            address,
             block_mesh: meshes.add(Mesh::from(shape::Cube { size: 1.0 })),
             blocks: HashMap::new(),
             last_tick: time.time_since_startup(),
+            paused: false,
         };
         Voxels::setup(&game, &mut commands, materials);
         commands.insert_resource(game);
@@ -160,9 +215,40 @@ impl Game {
             .insert(Collider::cuboid(0.5, 0.5, 0.5));
     }
 
-    fn tick(mut game: ResMut<Game>, time: Res<Time>) {
+    fn apply_inputs(mut game: ResMut<Game>, mut events: EventReader<CircuitInputEvent>) {
+        for event in events.iter() {
+            game.circuit.set_input(event.node, event.value);
+        }
+    }
+
+    /// Logs the block [Game::address] points at.
+    fn inspect_address(game: Res<Game>, keyboard_input: Res<Input<KeyCode>>) {
+        if keyboard_input.just_pressed(KeyCode::I) {
+            let address = game.address.read::<u32>(&game.circuit);
+            info!(
+                "Address {address:#x} is block {:?}",
+                address_to_coord(address)
+            );
+        }
+    }
+
+    fn tick(mut game: ResMut<Game>, time: Res<Time>, keyboard_input: Res<Input<KeyCode>>) {
         let now = time.time_since_startup();
-        if now - game.last_tick >= Duration::from_secs(1) {
+        if keyboard_input.just_pressed(KeyCode::P) {
+            game.paused = !game.paused;
+            info!(
+                "Simulation {}",
+                if game.paused { "paused" } else { "resumed" }
+            );
+        }
+        if game.paused {
+            // Single step while paused
+            if keyboard_input.just_pressed(KeyCode::N) {
+                game.circuit.run(1);
+                info!("Stepped to tick {}", game.circuit.tick());
+            }
+            game.last_tick = now;
+        } else if now - game.last_tick >= Duration::from_secs(1) {
             // TODO: This better
             game.circuit.run(1);
             game.last_tick = now;
@@ -290,6 +376,7 @@ impl Voxels {
 
     fn cursor_ray(
         game: Res<Game>,
+        mut input_events: EventWriter<CircuitInputEvent>,
         mut commands: Commands,
         mut materials: ResMut<Assets<StandardMaterial>>,
         mouse: Res<Input<MouseButton>>,
@@ -321,6 +408,18 @@ impl Voxels {
                             z as i32,
                             Color::WHITE,
                         );
+                        let coord = Coord {
+                            x: x as i32,
+                            y: y as i32,
+                            z: z as i32,
+                        };
+                        let address = coord_to_address(coord);
+                        for (bit, node) in game.address.iter().cloned().enumerate() {
+                            input_events.send(CircuitInputEvent {
+                                node,
+                                value: address & (1 << bit) != 0,
+                            });
+                        }
                     }
                 } else if destroy {
                     commands.entity(entity).despawn();
@@ -332,8 +431,11 @@ impl Voxels {
 
 impl Plugin for Voxels {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(Game::setup)
-            .add_system(Game::tick)
+        app.add_event::<CircuitInputEvent>()
+            .add_startup_system(Game::setup)
+            .add_system(Game::apply_inputs.label(ApplyInputs))
+            .add_system(Game::tick.after(ApplyInputs))
+            .add_system(Game::inspect_address)
             .add_system(Voxels::grab_mouse)
             .add_system(Voxels::cursor_ray)
             .add_system(CameraState::camera_movement);
@@ -350,3 +452,33 @@ fn main() {
         .add_plugin(bevy::diagnostic::FrameTimeDiagnosticsPlugin::default())
         .run();
 }
+
+#[cfg(test)]
+mod test {
+    use super::{address_to_coord, coord_to_address, Coord};
+
+    #[test]
+    fn address_round_trip_test() {
+        let coords = [
+            Coord { x: 0, y: 0, z: 0 },
+            Coord { x: 1, y: 2, z: 3 },
+            Coord {
+                x: -1,
+                y: -512,
+                z: 511,
+            },
+            Coord {
+                x: 300,
+                y: -7,
+                z: -300,
+            },
+        ];
+        for coord in coords {
+            assert_eq!(address_to_coord(coord_to_address(coord)), coord);
+        }
+        let addresses: Vec<_> = coords.into_iter().map(coord_to_address).collect();
+        for (i, address) in addresses.iter().enumerate() {
+            assert!(!addresses[..i].contains(address), "{address:#x} reused");
+        }
+    }
+}
@@ -1,14 +1,29 @@
 use std::{
-    collections::VecDeque,
-    fmt::Debug,
+    collections::{HashMap, HashSet, VecDeque},
+    error::Error,
+    fmt::{self, Debug, Display, Formatter},
+    mem::size_of,
     num::Wrapping,
-    ops::{Index, IndexMut},
+    ops::{Index, IndexMut, Range},
     sync::atomic::{AtomicU32, Ordering},
 };
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 use crate::circuit_sim::*;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+mod analysis;
+mod descriptor;
+mod dot;
+mod hierarchy;
+mod transform;
+pub use analysis::*;
+pub use descriptor::*;
+pub use transform::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct NodeId(u32);
 
 impl NodeId {
@@ -28,22 +43,62 @@ struct NodeIdBuilder {
 }
 
 impl NodeIdBuilder {
-    fn get_id(&mut self) -> NodeId {
-        let node_id = match self.unused.pop_front() {
-            Some(node_id) => node_id,
-            None => NodeId(self.next.fetch_add(1, Ordering::SeqCst)),
-        };
-        if node_id == NodeId::NULL {
-            panic!("Reached null node id");
+    fn try_get_id(&mut self) -> Result<NodeId, OutOfNodeIds> {
+        if let Some(node_id) = self.unused.pop_front() {
+            return Ok(node_id);
+        }
+        let next = self.next.get_mut();
+        if *next == NodeId::NULL.0 {
+            return Err(OutOfNodeIds);
+        }
+        let node_id = NodeId(*next);
+        *next += 1;
+        Ok(node_id)
+    }
+
+    /// Fresh contiguous ids, never reusing destroyed ones.
+    fn try_get_ids(&mut self, count: usize) -> Result<Range<u32>, OutOfNodeIds> {
+        let next = self.next.get_mut();
+        if count as u64 > (NodeId::NULL.0 - *next) as u64 {
+            return Err(OutOfNodeIds);
         }
-        node_id
+        let ids = *next..*next + count as u32;
+        *next = ids.end;
+        Ok(ids)
     }
 
-    fn _destroy_id(&mut self, node_id: NodeId) {
+    fn remaining_ids(&self) -> u64 {
+        let fresh = NodeId::NULL.0 - self.next.load(Ordering::SeqCst);
+        fresh as u64 + self.unused.len() as u64
+    }
+
+    fn destroy_id(&mut self, node_id: NodeId) {
         self.unused.push_back(node_id);
     }
 }
 
+// [AtomicU32] isn't [Clone]
+impl Clone for NodeIdBuilder {
+    fn clone(&self) -> Self {
+        NodeIdBuilder {
+            next: AtomicU32::new(self.next.load(Ordering::SeqCst)),
+            unused: self.unused.clone(),
+        }
+    }
+}
+
+/// Returned when a [Circuit] has handed out every [NodeId] it can represent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutOfNodeIds;
+
+impl Display for OutOfNodeIds {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Reached null node id, no more nodes can be created")
+    }
+}
+
+impl Error for OutOfNodeIds {}
+
 impl<T> Index<NodeId> for Vec<T> {
     type Output = T;
 
@@ -60,11 +115,13 @@ impl<T: Clone + Default> IndexMut<NodeId> for Vec<T> {
 
 #[repr(u8)]
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 enum GateType {
     #[default]
     OrNor,
     AndNand,
     XorXnor,
+    Const, // Output is fixed to `inverted`
 }
 
 // #[repr(align(8))]
@@ -89,7 +146,24 @@ struct UpdateData {
     inputs_delta: Wrapping<u8>,
 }
 
-#[derive(Default, Debug)]
+/// Callback given the tick and new output whenever a node's output changes.
+pub type Probe = Box<dyn FnMut(Tick, bool) + Send + Sync>;
+
+/// Probes set with [Circuit::set_probe]. Callbacks can't be cloned, so a
+/// cloned circuit starts out without any.
+#[derive(Default)]
+struct Probes(HashMap<NodeId, Probe>);
+
+impl Clone for Probes {
+    fn clone(&self) -> Self {
+        Probes::default()
+    }
+}
+
+/// Cloning copies the whole simulation state along with the connections, so
+/// the clone carries on from exactly where the original is. Probes aren't
+/// copied.
+#[derive(Clone, Default)]
 pub struct Circuit {
     node_id_builder: NodeIdBuilder,
 
@@ -100,6 +174,108 @@ pub struct Circuit {
     node_children: Vec<Vec<NodeId>>,
     node_data: Vec<NodeData>,
     node_update_data: Vec<UpdateData>,
+    // Deleted nodes still linked into a queue, whose ids can only be reused
+    // once an update has unlinked them
+    dying: Vec<NodeId>,
+    // Inputs set by [Circuit::hold_input], with the tick they revert on and
+    // the value they revert to
+    held: Vec<(Tick, NodeId, bool)>,
+    // Clock nodes with their period and the tick they next toggle on
+    clocks: Vec<(NodeId, Ticks, Tick)>,
+    stable_order: bool,
+    probes: Probes,
+    step_phase: StepPhase,
+    // Labels from [Circuit::mark_phase] with the tick they were marked on
+    phases: Vec<(Tick, String)>,
+    change_log: ChangeLog,
+    // The tick each node last changed or was created on, for
+    // [Circuit::stable_for]. Only kept after [Circuit::track_stability]
+    last_changed: Option<Vec<Tick>>,
+    hierarchy: hierarchy::Hierarchy,
+    // Names from [Circuit::set_node_name], for the exports
+    node_names: HashMap<NodeId, String>,
+}
+
+/// The latest output changes, for [Circuit::changes_since].
+#[derive(Clone, Debug, Default)]
+struct ChangeLog {
+    // Not logging when zero
+    capacity: usize,
+    entries: VecDeque<(Tick, NodeId)>,
+    // The tick of the latest change that was dropped to stay in capacity
+    dropped: Option<Tick>,
+}
+
+impl ChangeLog {
+    fn record(&mut self, tick: Tick, node_id: NodeId) {
+        if self.entries.len() == self.capacity {
+            if let Some((dropped, _)) = self.entries.pop_front() {
+                self.dropped = Some(dropped);
+            }
+        }
+        self.entries.push_back((tick, node_id));
+    }
+}
+
+/// How far through a tick [Circuit::micro_step] has got.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum StepPhase {
+    #[default]
+    Between,
+    Notifying,
+    Evaluating,
+}
+
+/// The simulation state of a [Circuit], saved by [Circuit::snapshot]. The
+/// connections aren't saved, so it can only be restored into the circuit it
+/// came from, or a copy of it, and only while the connections are unchanged.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CircuitSnapshot {
+    tick: Tick,
+    // Output and input count of each node
+    nodes: Vec<(bool, u8)>,
+    update_queue: Vec<NodeId>,
+    changed_queue: Vec<(NodeId, u8)>,
+    held: Vec<(Tick, NodeId, bool)>,
+    clock_toggles: Vec<Tick>,
+    step_phase: StepPhase,
+}
+
+/// How many nodes `{:#?}` prints in full before truncating.
+const DEBUG_MAX_NODES: usize = 64;
+
+impl Debug for Circuit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let alternate = f.alternate();
+        let num_edges: usize = self.node_children.iter().map(Vec::len).sum();
+        let mut debug = f.debug_struct("Circuit");
+        debug
+            .field("nodes", &self.num_nodes())
+            .field("edges", &num_edges)
+            .field("tick", &self.tick)
+            .field("work_left", &self.work_left());
+        if !alternate {
+            return debug.finish();
+        }
+
+        let shown: Vec<_> = self
+            .node_ids()
+            .take(DEBUG_MAX_NODES)
+            .map(|node_id| {
+                let node_data = &self.node_data[node_id];
+                let children = &self.node_children[node_id];
+                (node_id, node_data, children)
+            })
+            .collect();
+        debug.field("node_data", &shown);
+        if self.node_data.len() > DEBUG_MAX_NODES {
+            debug.finish_non_exhaustive()
+        } else {
+            debug.finish()
+        }
+    }
 }
 
 macro_rules! enqueue {
@@ -126,6 +302,8 @@ impl Circuit {
         );
     }
 
+    // Takes a [Vec] rather than a slice to be indexable by [NodeId]
+    #[allow(clippy::ptr_arg)]
     fn modify(
         node_update_data: &mut Vec<UpdateData>,
         changed_head: &mut NodeId,
@@ -143,8 +321,30 @@ impl Circuit {
         enqueue!(*changed_head, update_data.next_changed, node_id);
     }
 
-    fn add_node(&mut self, gate_type: GateType, inverted: bool) -> NodeId {
-        let node_id = self.node_id_builder.get_id();
+    /// Changes the output of a node without evaluating it, propagating the
+    /// change to its children on the next update.
+    fn set_output(&mut self, node_id: NodeId, val: bool) {
+        let output = &mut self.node_data[node_id].output;
+        if *output != val {
+            *output = val;
+            self.output_changed(node_id, val);
+            for child in self.node_children[node_id].iter().cloned() {
+                Self::modify(
+                    &mut self.node_update_data,
+                    &mut self.changed_head,
+                    child,
+                    val,
+                );
+            }
+        }
+    }
+
+    fn try_add_node(
+        &mut self,
+        gate_type: GateType,
+        inverted: bool,
+    ) -> Result<NodeId, OutOfNodeIds> {
+        let node_id = self.node_id_builder.try_get_id()?;
         let index = node_id.0 as usize;
         if index >= self.node_data.len() {
             self.node_children.resize(index + 1, Vec::new());
@@ -152,10 +352,655 @@ impl Circuit {
             self.node_update_data
                 .resize(index + 1, UpdateData::default());
         }
-        self.node_data[index].inverted = inverted;
-        self.node_data[index].output = inverted;
-        self.node_data[index].gate_type = gate_type;
-        node_id
+        // Recycled ids still hold whatever the deleted node left behind
+        self.node_data[index] = NodeData {
+            inverted,
+            output: inverted,
+            gate_type,
+            ..NodeData::default()
+        };
+        self.node_update_data[index] = UpdateData::default();
+        self.node_created(node_id);
+        Ok(node_id)
+    }
+
+    /// Walks a queue from `head`. The last node links to [NodeId::NULL] just
+    /// like a node that isn't queued, so membership can't be read off a
+    /// single node. A node enqueued again while last links back into the
+    /// queue, so the walk stops at the first repeat.
+    fn walk_queue(mut node_id: NodeId, next: impl Fn(NodeId) -> NodeId) -> Vec<NodeId> {
+        let mut seen = HashSet::new();
+        let mut queue = Vec::new();
+        while node_id != NodeId::NULL && seen.insert(node_id) {
+            queue.push(node_id);
+            node_id = next(node_id);
+        }
+        queue
+    }
+
+    fn update_queue(&self) -> Vec<NodeId> {
+        Self::walk_queue(self.update_head, |node_id| {
+            self.node_data[node_id].next_update
+        })
+    }
+
+    fn changed_queue(&self) -> Vec<NodeId> {
+        Self::walk_queue(self.changed_head, |node_id| {
+            self.node_update_data[node_id].next_changed
+        })
+    }
+
+    /// Replaces the update queue with `queue`, in order. Nodes that were
+    /// queued but aren't in `queue` must have been unlinked already.
+    fn link_update_queue(&mut self, queue: &[NodeId]) {
+        // Linked back to front so the queue comes out in the same order
+        self.update_head = NodeId::NULL;
+        for node_id in queue.iter().rev().cloned() {
+            self.node_data[node_id].next_update = self.update_head;
+            self.update_head = node_id;
+        }
+    }
+
+    /// Like [Self::link_update_queue], for the changed queue. Input deltas
+    /// are left as they are.
+    fn link_changed_queue(&mut self, queue: &[NodeId]) {
+        self.changed_head = NodeId::NULL;
+        for node_id in queue.iter().rev().cloned() {
+            self.node_update_data[node_id].next_changed = self.changed_head;
+            self.changed_head = node_id;
+        }
+    }
+
+    /// Reverts due held inputs and toggles due clocks, then readies the
+    /// update queue for notifying children.
+    fn start_tick(&mut self) {
+        if !self.held.is_empty() {
+            let mut held = std::mem::take(&mut self.held);
+            held.retain(|(until, input, val)| {
+                if *until > self.tick {
+                    return true;
+                }
+                self.set_input(*input, *val);
+                false
+            });
+            self.held = held;
+        }
+        for i in 0..self.clocks.len() {
+            let (clock, period, next_toggle) = self.clocks[i];
+            if next_toggle <= self.tick {
+                self.set_output(clock, !self.get_output(clock));
+                self.clocks[i].2 = next_toggle + period;
+            }
+        }
+
+        if self.stable_order {
+            let mut queue = self.update_queue();
+            queue.sort_unstable();
+            self.link_update_queue(&queue);
+        }
+        self.step_phase = StepPhase::Notifying;
+    }
+
+    /// Readies the changed queue once every child has been notified.
+    fn start_evaluating(&mut self) {
+        if self.stable_order {
+            let mut queue = self.changed_queue();
+            queue.sort_unstable();
+            self.link_changed_queue(&queue);
+        }
+        self.step_phase = StepPhase::Evaluating;
+    }
+
+    /// Phase one for a single node: tells its children its new output.
+    #[inline(always)]
+    fn notify_children(&mut self, node_id: NodeId) {
+        let node_output = self.node_data[node_id].output;
+        for child in self.node_children[node_id].iter().cloned() {
+            Self::modify(
+                &mut self.node_update_data,
+                &mut self.changed_head,
+                child,
+                node_output,
+            );
+        }
+    }
+
+    /// Phase two for a single node: applies its input delta and queues it if
+    /// its output changed. The node must already be unlinked.
+    #[inline(always)]
+    fn evaluate(&mut self, node_id: NodeId) {
+        let node_update_data = &mut self.node_update_data[node_id];
+        if node_update_data.inputs_delta.0 == 0 {
+            return;
+        }
+        let node_data = &mut self.node_data[node_id];
+        match node_data.gate_type {
+            GateType::OrNor | GateType::AndNand => {
+                node_data.inputs += node_update_data.inputs_delta
+            }
+            // Each toggled input adds or subtracts one, so the low
+            // bit of the delta is the parity of how many toggled
+            GateType::XorXnor => node_data.inputs ^= node_update_data.inputs_delta.0 & 1,
+            GateType::Const => unreachable!("Constant nodes have no inputs"),
+        }
+        node_update_data.inputs_delta = Wrapping(0);
+        let new_output = node_data.inverted ^ (node_data.inputs.0 != 0);
+        if node_data.output != new_output {
+            node_data.output = new_output;
+            self.enqueue_update(node_id);
+            self.output_changed(node_id, new_output);
+        }
+    }
+
+    /// Tells probes and the change log that the output of a node changed.
+    #[inline(always)]
+    fn output_changed(&mut self, node_id: NodeId, val: bool) {
+        if !self.probes.0.is_empty() {
+            if let Some(probe) = self.probes.0.get_mut(&node_id) {
+                probe(self.tick, val);
+            }
+        }
+        if self.change_log.capacity != 0 {
+            self.change_log.record(self.tick, node_id);
+        }
+        if let Some(last_changed) = &mut self.last_changed {
+            last_changed[node_id] = self.tick;
+        }
+    }
+
+    /// Puts a new or recycled node in the innermost open module, and starts
+    /// it off as stable from now.
+    fn node_created(&mut self, node_id: NodeId) {
+        self.hierarchy.node_created(node_id);
+        if let Some(last_changed) = &mut self.last_changed {
+            last_changed.resize(self.node_data.len(), self.tick);
+            last_changed[node_id] = self.tick;
+        }
+    }
+
+    /// Starts keeping the tick every node last changed on, for
+    /// [Self::stable_for], with every node counting as stable from now.
+    /// `false` stops. Tracking costs a [Tick] per node and a write on every
+    /// change.
+    pub fn track_stability(&mut self, track: bool) {
+        self.last_changed = track.then(|| vec![self.tick; self.node_data.len()]);
+    }
+
+    /// How many ticks since the output of the node last changed, or since
+    /// it was created or [Self::track_stability] was called if it hasn't,
+    /// for checking a result has settled for long enough before sampling
+    /// it. Snapshots don't save when nodes changed, so after [Self::restore]
+    /// every node counts from the restored tick.
+    pub fn stable_for(&self, node_id: NodeId) -> Ticks {
+        let last_changed = self
+            .last_changed
+            .as_ref()
+            .expect("Stability isn't tracked, call track_stability first");
+        self.tick - last_changed[node_id]
+    }
+
+    /// Releases dying nodes that have left the queues and moves on a tick.
+    fn finish_tick(&mut self) {
+        // Having no children, dying nodes leave the queues within two updates
+        if !self.dying.is_empty() {
+            // Phase two emptied the changed queue
+            let update_queue = self.update_queue();
+            let mut dying = std::mem::take(&mut self.dying);
+            dying.retain(|node_id| {
+                let queued = update_queue.contains(node_id);
+                if !queued {
+                    self.node_id_builder.destroy_id(*node_id);
+                }
+                queued
+            });
+            self.dying = dying;
+        }
+
+        self.tick += 1;
+        self.step_phase = StepPhase::Between;
+    }
+
+    /// Processes a single queued node, the way [CircuitSim::update] would
+    /// have, and returns it. A tick first notifies the children of every
+    /// node whose output changed, then evaluates every node with changed
+    /// inputs, so stepping through a tick gives those nodes in that order.
+    /// Returns [None] once there's no work left. Can be freely mixed with
+    /// [CircuitSim::update], which finishes a partly stepped tick.
+    pub fn micro_step(&mut self) -> Option<NodeId> {
+        loop {
+            match self.step_phase {
+                StepPhase::Between => {
+                    if !self.work_left() {
+                        return None;
+                    }
+                    self.start_tick();
+                }
+                StepPhase::Notifying => {
+                    let node_id = self.update_head;
+                    if node_id == NodeId::NULL {
+                        self.start_evaluating();
+                        continue;
+                    }
+                    let node_data = &mut self.node_data[node_id];
+                    self.update_head = node_data.next_update;
+                    node_data.next_update = NodeId::NULL;
+                    self.notify_children(node_id);
+                    return Some(node_id);
+                }
+                StepPhase::Evaluating => {
+                    let node_id = self.changed_head;
+                    if node_id == NodeId::NULL {
+                        self.finish_tick();
+                        continue;
+                    }
+                    let node_update_data = &mut self.node_update_data[node_id];
+                    self.changed_head = node_update_data.next_changed;
+                    node_update_data.next_changed = NodeId::NULL;
+                    self.evaluate(node_id);
+                    return Some(node_id);
+                }
+            }
+        }
+    }
+
+    /// Labels the current tick as the start of a phase like "fetch" or
+    /// "execute", so traces of multi-cycle operations are easier to follow.
+    /// Exporters like [crate::vcd::VcdWriter] include the marks in their
+    /// output.
+    pub fn mark_phase(&mut self, name: &str) {
+        self.phases.push((self.tick, name.to_string()));
+    }
+
+    /// Every phase marked so far, with the tick it was marked on.
+    pub fn phases(&self) -> &[(Tick, String)] {
+        &self.phases
+    }
+
+    /// Starts logging output changes so [Self::changes_since] can tell which
+    /// nodes changed, keeping at most `capacity` of the latest changes.
+    /// Zero stops logging. Logging costs a little on every change, and the
+    /// log starts out empty.
+    pub fn track_changes(&mut self, capacity: usize) {
+        self.change_log = ChangeLog {
+            capacity,
+            ..ChangeLog::default()
+        };
+    }
+
+    /// Every node whose output is different from what it was at `tick`,
+    /// with its output now, for redrawing only what changed. Changes are
+    /// counted from when [Self::track_changes] was called, and if the log
+    /// has since dropped changes made at or after `tick`, every node is
+    /// returned instead. Panics if changes aren't being tracked.
+    pub fn changes_since(&self, tick: Tick) -> Vec<(NodeId, bool)> {
+        assert!(
+            self.change_log.capacity > 0,
+            "Changes aren't tracked, call track_changes first"
+        );
+        if self
+            .change_log
+            .dropped
+            .is_some_and(|dropped| dropped >= tick)
+        {
+            return self
+                .node_ids()
+                .map(|node_id| (node_id, self.get_output(node_id)))
+                .collect();
+        }
+        // Every change toggles the output, so only an odd number of them
+        // leaves it different
+        let mut toggled = HashMap::new();
+        for (_, node_id) in self
+            .change_log
+            .entries
+            .iter()
+            .rev()
+            .take_while(|(change_tick, _)| *change_tick >= tick)
+        {
+            *toggled.entry(*node_id).or_insert(false) ^= true;
+        }
+        let mut changes: Vec<_> = toggled
+            .into_iter()
+            .filter(|(_, odd)| *odd)
+            .map(|(node_id, _)| (node_id, self.get_output(node_id)))
+            .collect();
+        changes.sort_unstable();
+        changes
+    }
+
+    /// Calls `probe` with the tick and the new output every time the output
+    /// of the node changes, replacing any probe already on it. Probes cost a
+    /// lookup on every change while any are set, so use them sparingly.
+    pub fn set_probe(&mut self, node_id: NodeId, probe: Probe) {
+        self.probes.0.insert(node_id, probe);
+    }
+
+    pub fn remove_probe(&mut self, node_id: NodeId) {
+        self.probes.0.remove(&node_id);
+    }
+
+    /// When enabled, every update processes queued nodes in ascending
+    /// [NodeId] order, rather than in the order they were queued. That
+    /// order depends on the order nodes were connected in, so this makes the
+    /// changes within each tick reproducible across builds of the same
+    /// circuit, at the cost of sorting the queues every tick. Settled states
+    /// are the same either way.
+    pub fn set_stable_order(&mut self, stable_order: bool) {
+        self.stable_order = stable_order;
+    }
+
+    /// Takes back the input of `input` that `output` has counted, after
+    /// they've been disconnected. `pending` is whether `input` is in the
+    /// update queue.
+    fn uncount_input(&mut self, input: NodeId, output: NodeId, pending: bool) {
+        // If the input changed last tick, its children haven't been told yet
+        // and still count its old output
+        let counted = self.get_output(input) ^ pending;
+        let is_and_nand = self.node_data[output].gate_type == GateType::AndNand;
+        if counted ^ is_and_nand {
+            Self::modify(
+                &mut self.node_update_data,
+                &mut self.changed_head,
+                output,
+                is_and_nand,
+            );
+        }
+    }
+
+    fn add_node(&mut self, gate_type: GateType, inverted: bool) -> NodeId {
+        self.try_add_node(gate_type, inverted)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    fn gate_of(node_type: NodeType) -> (GateType, bool) {
+        match node_type {
+            NodeType::Or => (GateType::OrNor, false),
+            NodeType::Nor => (GateType::OrNor, true),
+            NodeType::And => (GateType::AndNand, true),
+            NodeType::Nand => (GateType::AndNand, false),
+            NodeType::Xor => (GateType::XorXnor, false),
+            NodeType::Xnor => (GateType::XorXnor, true),
+            NodeType::Buffer => (GateType::OrNor, false),
+            NodeType::Not => (GateType::OrNor, true),
+            NodeType::Const(val) => (GateType::Const, val),
+        }
+    }
+
+    /// Creates `count` nodes of the same type with contiguous ids, growing
+    /// the circuit once rather than per node.
+    pub fn create_nodes(&mut self, node_type: NodeType, count: usize) -> Vec<NodeId> {
+        let (gate_type, inverted) = Self::gate_of(node_type);
+        let ids = self
+            .node_id_builder
+            .try_get_ids(count)
+            .unwrap_or_else(|err| panic!("{err}"));
+        let len = ids.end as usize;
+        if len > self.node_data.len() {
+            self.node_children.resize(len, Vec::new());
+            self.node_data.resize(len, NodeData::default());
+            self.node_update_data.resize(len, UpdateData::default());
+        }
+        ids.map(|id| {
+            let node_data = &mut self.node_data[id as usize];
+            node_data.inverted = inverted;
+            node_data.output = inverted;
+            node_data.gate_type = gate_type;
+            self.node_created(NodeId(id));
+            NodeId(id)
+        })
+        .collect()
+    }
+
+    /// Gives the node a readable name, which [Circuit::to_dot] labels it
+    /// with and [crate::export::verilog::to_verilog] names its wire after.
+    pub fn set_node_name(&mut self, node_id: NodeId, name: impl Into<String>) {
+        self.node_names.insert(node_id, name.into());
+    }
+
+    /// The name given by [Circuit::set_node_name], if any.
+    pub fn node_name(&self, node_id: NodeId) -> Option<&str> {
+        self.node_names.get(&node_id).map(String::as_str)
+    }
+
+    /// The [NodeType] the node was created as. Buffers and inverters are
+    /// plain OR and NOR gates underneath, so they come back as
+    /// [NodeType::Or] and [NodeType::Nor].
+    pub fn node_kind(&self, node_id: NodeId) -> NodeType {
+        let node_data = &self.node_data[node_id];
+        match (node_data.gate_type, node_data.inverted) {
+            (GateType::OrNor, false) => NodeType::Or,
+            (GateType::OrNor, true) => NodeType::Nor,
+            (GateType::AndNand, true) => NodeType::And,
+            (GateType::AndNand, false) => NodeType::Nand,
+            (GateType::XorXnor, false) => NodeType::Xor,
+            (GateType::XorXnor, true) => NodeType::Xnor,
+            (GateType::Const, val) => NodeType::Const(val),
+        }
+    }
+
+    /// Like [CircuitSim::create_node], but fails gracefully instead of
+    /// panicking once every [NodeId] is in use.
+    pub fn try_create_node(&mut self, node_type: NodeType) -> Result<NodeId, OutOfNodeIds> {
+        let (gate_type, inverted) = Self::gate_of(node_type);
+        self.try_add_node(gate_type, inverted)
+    }
+
+    /// Sets an input for the next `ticks` updates, after which it goes back
+    /// to the value it had before, overriding any [CircuitSim::set_input]
+    /// in between. The circuit has work left until the input reverts.
+    pub fn hold_input(&mut self, input: NodeId, val: bool, ticks: Ticks) {
+        let previous = self.get_output(input);
+        self.set_input(input, val);
+        self.held.push((self.tick + ticks, input, previous));
+    }
+
+    /// Whether the node was created as a [NodeType::Const].
+    pub fn is_const(&self, node_id: NodeId) -> bool {
+        self.node_data[node_id].gate_type == GateType::Const
+    }
+
+    /// How many more nodes can be created before running out of [NodeId]s.
+    pub fn remaining_ids(&self) -> u64 {
+        self.node_id_builder.remaining_ids()
+    }
+
+    /// Saves the state of every node and the pending updates, to go back to
+    /// with [Self::restore]. Much cheaper than building the circuit again.
+    pub fn snapshot(&self) -> CircuitSnapshot {
+        CircuitSnapshot {
+            tick: self.tick,
+            nodes: self
+                .node_data
+                .iter()
+                .map(|node_data| (node_data.output, node_data.inputs.0))
+                .collect(),
+            update_queue: self.update_queue(),
+            changed_queue: self
+                .changed_queue()
+                .into_iter()
+                .map(|node_id| (node_id, self.node_update_data[node_id].inputs_delta.0))
+                .collect(),
+            held: self.held.clone(),
+            clock_toggles: self.clocks.iter().map(|(_, _, next)| *next).collect(),
+            step_phase: self.step_phase,
+        }
+    }
+
+    /// Puts the circuit back in the state saved by [Self::snapshot]. The
+    /// connections must not have changed since.
+    pub fn restore(&mut self, snapshot: &CircuitSnapshot) {
+        assert!(
+            snapshot.nodes.len() == self.node_data.len()
+                && snapshot.clock_toggles.len() == self.clocks.len(),
+            "Snapshot is of a different circuit"
+        );
+        // Snapshots can be deserialized, and queued ids index unchecked
+        if let Err(err) = snapshot.check(self.node_data.len(), self.clocks.len()) {
+            panic!("Invalid snapshot: {err}");
+        }
+        for node_id in self.update_queue() {
+            self.node_data[node_id].next_update = NodeId::NULL;
+        }
+        for node_id in self.changed_queue() {
+            let update_data = &mut self.node_update_data[node_id];
+            update_data.next_changed = NodeId::NULL;
+            update_data.inputs_delta = Wrapping(0);
+        }
+
+        for (node_data, (output, inputs)) in self.node_data.iter_mut().zip(&snapshot.nodes) {
+            node_data.output = *output;
+            node_data.inputs = Wrapping(*inputs);
+        }
+        self.link_update_queue(&snapshot.update_queue);
+        let changed_queue: Vec<_> = snapshot
+            .changed_queue
+            .iter()
+            .map(|(node_id, delta)| {
+                self.node_update_data[*node_id].inputs_delta = Wrapping(*delta);
+                *node_id
+            })
+            .collect();
+        self.link_changed_queue(&changed_queue);
+
+        self.tick = snapshot.tick;
+        self.step_phase = snapshot.step_phase;
+        // The log can't be rewound along with the outputs
+        self.change_log.entries.clear();
+        self.change_log.dropped = None;
+        if let Some(last_changed) = &mut self.last_changed {
+            last_changed.fill(snapshot.tick);
+        }
+        self.held = snapshot.held.clone();
+        for (clock, next) in self.clocks.iter_mut().zip(&snapshot.clock_toggles) {
+            clock.2 = *next;
+        }
+    }
+
+    /// Recounts every node's inputs from the outputs connected into it and
+    /// fixes up any node whose output disagrees, then runs until stable. A
+    /// safety net for when the cached state may not match the connections,
+    /// like after restoring a snapshot into a changed circuit. Pending
+    /// updates are dropped, since the recount already includes them. Nodes
+    /// nothing is connected into keep their outputs, as do latches that
+    /// hold a consistent value.
+    pub fn evaluate_all(&mut self) {
+        for node_id in self.update_queue() {
+            self.node_data[node_id].next_update = NodeId::NULL;
+        }
+        self.update_head = NodeId::NULL;
+        for node_id in self.changed_queue() {
+            self.node_update_data[node_id].next_changed = NodeId::NULL;
+        }
+        self.changed_head = NodeId::NULL;
+
+        let mut has_parent = vec![false; self.node_data.len()];
+        let mut inputs = vec![Wrapping(0u8); self.node_data.len()];
+        for (input, output) in self.edges() {
+            has_parent[output] = true;
+            // The same counts [CircuitSim::connect] keeps: high inputs count
+            // up, and low inputs into an AND count down
+            match self.node_data[output].gate_type {
+                GateType::OrNor | GateType::XorXnor if self.get_output(input) => {
+                    inputs[output] += 1
+                }
+                GateType::AndNand if !self.get_output(input) => inputs[output] -= 1,
+                GateType::Const => unreachable!("Constant nodes have no inputs"),
+                _ => {}
+            }
+        }
+
+        let node_ids: Vec<_> = self.node_ids().collect();
+        for node_id in node_ids.iter().cloned() {
+            self.node_update_data[node_id].inputs_delta = Wrapping(0);
+            let node_data = &mut self.node_data[node_id];
+            node_data.inputs = match node_data.gate_type {
+                GateType::XorXnor => inputs[node_id] & Wrapping(1),
+                _ => inputs[node_id],
+            };
+        }
+        for node_id in node_ids {
+            let node_data = &self.node_data[node_id];
+            let evaluated = node_data.inverted ^ (node_data.inputs.0 != 0);
+            if has_parent[node_id] && node_data.output != evaluated {
+                self.set_output(node_id, evaluated);
+            }
+        }
+        self.run_until_done();
+    }
+
+    /// Estimated heap memory used by the circuit in bytes, counting the
+    /// capacity of the per-node vectors rather than just what's in use.
+    pub fn memory_bytes(&self) -> usize {
+        let children: usize = self
+            .node_children
+            .iter()
+            .map(|children| children.capacity() * size_of::<NodeId>())
+            .sum();
+        self.node_data.capacity() * size_of::<NodeData>()
+            + self.node_update_data.capacity() * size_of::<UpdateData>()
+            + self.node_children.capacity() * size_of::<Vec<NodeId>>()
+            + self.last_changed.as_ref().map_or(0, |last_changed| {
+                last_changed.capacity() * size_of::<Tick>()
+            })
+            + children
+    }
+
+    /// Every connection in the circuit as an `(input, output)` pair.
+    pub fn edges(&self) -> impl Iterator<Item = (NodeId, NodeId)> + '_ {
+        self.node_ids().flat_map(move |node_id| {
+            self.node_children[node_id]
+                .iter()
+                .map(move |child| (node_id, *child))
+        })
+    }
+
+    /// Every [NodeId] in the circuit, in the order they were created.
+    /// Deleted nodes are left out until their id is reused.
+    pub fn node_ids(&self) -> impl Iterator<Item = NodeId> + '_ {
+        let deleted = self.deleted_node_ids();
+        (0..self.node_data.len() as u32)
+            .map(NodeId)
+            .filter(move |node_id| !deleted.contains(node_id))
+    }
+
+    /// Ids of deleted nodes, whether free to reuse or still queued.
+    fn deleted_node_ids(&self) -> HashSet<NodeId> {
+        self.node_id_builder
+            .unused
+            .iter()
+            .chain(self.dying.iter())
+            .cloned()
+            .collect()
+    }
+
+    /// Inverse of [Self::node_children], built on demand since it's only
+    /// needed by analyses and transforms.
+    fn node_parents(&self) -> Vec<Vec<NodeId>> {
+        let mut parents = vec![Vec::new(); self.node_data.len()];
+        for node_id in self.node_ids() {
+            for child in self.node_children[node_id].iter().cloned() {
+                parents[child].push(node_id);
+            }
+        }
+        parents
+    }
+
+    /// The input counter of a [GateType] whose parents have these outputs,
+    /// matching the bookkeeping done by [Self::connect] and [Self::update].
+    fn count_inputs(
+        gate_type: GateType,
+        parent_outputs: impl Iterator<Item = bool>,
+    ) -> Wrapping<u8> {
+        let mut inputs = Wrapping(0);
+        for output in parent_outputs {
+            match gate_type {
+                GateType::OrNor if output => inputs += 1,
+                GateType::AndNand if !output => inputs -= 1,
+                GateType::XorXnor if output => inputs ^= 1,
+                _ => {}
+            }
+        }
+        inputs
     }
 }
 
@@ -172,26 +1017,25 @@ impl CircuitSim for Circuit {
     }
 
     fn num_nodes(&self) -> usize {
-        self.node_data.len()
+        self.node_data.len() - self.node_id_builder.unused.len() - self.dying.len()
     }
 
     fn update(&mut self) {
-        let mut node_id = self.update_head;
-        self.update_head = NodeId::NULL;
-        while node_id != NodeId::NULL {
-            let node_data = &mut self.node_data[node_id];
-            let node_output = node_data.output;
-            let next_update = node_data.next_update;
-            node_data.next_update = NodeId::NULL;
-            for child in self.node_children[node_id].iter().cloned() {
-                Self::modify(
-                    &mut self.node_update_data,
-                    &mut self.changed_head,
-                    child,
-                    node_output,
-                );
+        // Finishes whatever tick [Circuit::micro_step] left half done
+        if self.step_phase == StepPhase::Between {
+            self.start_tick();
+        }
+        if self.step_phase != StepPhase::Evaluating {
+            let mut node_id = self.update_head;
+            self.update_head = NodeId::NULL;
+            while node_id != NodeId::NULL {
+                let node_data = &mut self.node_data[node_id];
+                let next_update = node_data.next_update;
+                node_data.next_update = NodeId::NULL;
+                self.notify_children(node_id);
+                node_id = next_update;
             }
-            node_id = next_update;
+            self.start_evaluating();
         }
 
         let mut node_id = self.changed_head;
@@ -200,75 +1044,136 @@ impl CircuitSim for Circuit {
             let node_update_data = &mut self.node_update_data[node_id];
             let next_changed = node_update_data.next_changed;
             node_update_data.next_changed = NodeId::NULL;
-            if node_update_data.inputs_delta.0 != 0 {
-                let node_data = &mut self.node_data[node_id];
-                match node_data.gate_type {
-                    GateType::OrNor | GateType::AndNand => {
-                        node_data.inputs += node_update_data.inputs_delta
-                    }
-                    GateType::XorXnor => node_data.inputs ^= node_update_data.inputs_delta.0 & 1,
-                }
-                node_update_data.inputs_delta = Wrapping(0);
-                let new_output = node_data.inverted ^ (node_data.inputs.0 != 0);
-                if node_data.output != new_output {
-                    node_data.output = new_output;
-                    self.enqueue_update(node_id);
-                }
-            }
+            self.evaluate(node_id);
             node_id = next_changed;
         }
 
-        self.tick += 1;
+        self.finish_tick();
+    }
+
+    fn pending_nodes(&self) -> Vec<NodeId> {
+        let mut pending = self.update_queue();
+        let mut seen = vec![false; self.node_data.len()];
+        for node_id in pending.iter().cloned() {
+            seen[node_id] = true;
+        }
+        for node_id in self.changed_queue() {
+            if !seen[node_id] {
+                seen[node_id] = true;
+                pending.push(node_id);
+            }
+        }
+        pending
     }
 
     fn work_left(&self) -> bool {
-        self.update_head != NodeId::NULL || self.changed_head != NodeId::NULL
+        self.update_head != NodeId::NULL
+            || self.changed_head != NodeId::NULL
+            || !self.held.is_empty()
     }
 
     fn create_node(&mut self, node_type: NodeType) -> Self::NodeId {
-        match node_type {
-            NodeType::Or => self.add_node(GateType::OrNor, false),
-            NodeType::Nor => self.add_node(GateType::OrNor, true),
-            NodeType::And => self.add_node(GateType::AndNand, true),
-            NodeType::Nand => self.add_node(GateType::AndNand, false),
-            NodeType::Xor => self.add_node(GateType::XorXnor, false),
-            NodeType::Xnor => self.add_node(GateType::XorXnor, true),
-        }
+        let (gate_type, inverted) = Self::gate_of(node_type);
+        self.add_node(gate_type, inverted)
     }
 
     fn create_input(&mut self) -> Self::InputId {
         self.create_node(NodeType::Or)
     }
 
+    fn clock(&mut self, period_ticks: Ticks) -> NodeId {
+        assert!(period_ticks > 0, "Clock period must be at least one tick");
+        let clock = self.create_node(NodeType::Or);
+        self.clocks
+            .push((clock, period_ticks, self.tick + period_ticks));
+        clock
+    }
+
     fn set_input(&mut self, node_id: NodeId, val: bool) {
-        let output = &mut self.node_data[node_id].output;
-        if *output != val {
-            *output = val;
-            for child in self.node_children[node_id].iter().cloned() {
-                Self::modify(
-                    &mut self.node_update_data,
-                    &mut self.changed_head,
-                    child,
-                    val,
-                );
-            }
-            // self.enqueue_update(node_id);
-        }
+        assert!(
+            !self.is_const(node_id),
+            "Can't set constant node {node_id:?}"
+        );
+        self.set_output(node_id, val);
     }
 
     fn connect(&mut self, input: NodeId, output: NodeId) {
+        // Usually an off-by-one while generating, and an OR into itself
+        // latches high forever while anything inverting oscillates
+        debug_assert!(input != output, "Can't connect {input:?} into itself");
         self.node_children[input].push(output);
         let is_and_nand = match self.node_data[output].gate_type {
             GateType::OrNor | GateType::XorXnor => false,
             GateType::AndNand => true,
+            GateType::Const => panic!("Can't connect into constant node {output:?}"),
         };
-        if self.get_output(input) ^ is_and_nand {
+        if !(self.get_output(input) ^ is_and_nand) {
+            return;
+        }
+
+        if self.update_head != NodeId::NULL {
+            // The output might be waiting to be propagated, in which case
+            // changing it now would notify its children twice
             Self::modify(
                 &mut self.node_update_data,
                 &mut self.changed_head,
                 output,
                 !is_and_nand,
             );
+            return;
+        }
+        // Otherwise evaluate right away, so the output reads correctly after
+        // every connect instead of after the next update. This matters for
+        // AND gates, which read high until their first low input arrives.
+        let node_data = &mut self.node_data[output];
+        match node_data.gate_type {
+            GateType::OrNor => node_data.inputs += 1,
+            GateType::AndNand => node_data.inputs -= 1,
+            GateType::XorXnor => node_data.inputs ^= 1,
+            GateType::Const => unreachable!(),
+        }
+        let new_output = node_data.inverted ^ (node_data.inputs.0 != 0);
+        self.set_output(output, new_output);
+    }
+
+    fn disconnect(&mut self, input: NodeId, output: NodeId) {
+        let children = &mut self.node_children[input];
+        let Some(position) = children.iter().position(|child| *child == output) else {
+            return;
+        };
+        children.remove(position);
+        let pending = self.update_queue().contains(&input);
+        self.uncount_input(input, output, pending);
+    }
+
+    fn delete_node(&mut self, node_id: NodeId) {
+        debug_assert!(
+            !self.node_id_builder.unused.contains(&node_id) && !self.dying.contains(&node_id),
+            "{node_id:?} was already deleted"
+        );
+        let update_queue = self.update_queue();
+        let pending = update_queue.contains(&node_id);
+        for child in std::mem::take(&mut self.node_children[node_id]) {
+            if child != node_id {
+                self.uncount_input(node_id, child, pending);
+            }
+        }
+        for children in self.node_children.iter_mut() {
+            children.retain(|child| *child != node_id);
+        }
+        self.held.retain(|(_, input, _)| *input != node_id);
+        self.clocks.retain(|(clock, _, _)| *clock != node_id);
+        self.probes.0.remove(&node_id);
+        self.hierarchy.node_deleted(node_id);
+        self.node_names.remove(&node_id);
+        self.change_log
+            .entries
+            .retain(|(_, changed)| *changed != node_id);
+
+        if pending || self.changed_queue().contains(&node_id) {
+            self.dying.push(node_id);
+        } else {
+            self.node_id_builder.destroy_id(node_id);
         }
     }
 
@@ -276,3 +1181,128 @@ impl CircuitSim for Circuit {
         self.node_data[node_id].output
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, sync::Arc};
+
+    use rand::Rng;
+
+    use crate::{
+        circuit_builder::{CircuitBuilder, Connector},
+        circuit_sim::{CircuitSim, NodeType},
+        components::adder::RippleCarryAdder,
+        test_utils::seeded_rng,
+    };
+
+    use super::{Circuit, NodeId};
+
+    /// A 4-bit adder set to add `a` and `b`, left settled or not.
+    fn adder(a: u8, b: u8, settle: bool) -> (Circuit, Vec<NodeId>) {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let rca = RippleCarryAdder::<4>::new(builder.clone(), Connector::new(builder.clone()));
+        let mut circuit = builder.borrow().circuit.clone();
+        for i in 0..4 {
+            circuit.set_input(rca.input_a[i], a & (1 << i) != 0);
+            circuit.set_input(rca.input_b[i], b & (1 << i) != 0);
+        }
+        if settle {
+            circuit.run_until_done();
+        }
+        let mut outputs = rca.sum.to_vec();
+        outputs.push(rca.cout);
+        (circuit, outputs)
+    }
+
+    #[test]
+    fn evaluate_all_test() {
+        let mut rng = seeded_rng();
+        for _ in 0..8 {
+            let (a, b) = (rng.gen_range(0..16), rng.gen_range(0..16));
+            let (fresh, outputs) = adder(a, b, true);
+            let (mut scrambled, _) = adder(a, b, false);
+            let has_parent: Vec<_> = scrambled.edges().map(|(_, output)| output).collect();
+            for node_id in has_parent {
+                let node_data = &mut scrambled.node_data[node_id];
+                node_data.output = rng.gen();
+                node_data.inputs.0 = rng.gen();
+            }
+
+            scrambled.evaluate_all();
+            assert!(scrambled.is_stable());
+            for node_id in fresh.node_ids() {
+                assert_eq!(
+                    scrambled.get_output(node_id),
+                    fresh.get_output(node_id),
+                    "{node_id:?} of {a} + {b}"
+                );
+            }
+            let sum = outputs
+                .iter()
+                .enumerate()
+                .map(|(i, output)| (scrambled.get_output(*output) as u8) << i)
+                .sum::<u8>();
+            assert_eq!(sum, a + b);
+        }
+    }
+
+    /// Inverters fanning out of an input and back into an OR, with the
+    /// connections made in the given order.
+    fn fan_out_in(order: [usize; 3]) -> (Circuit, NodeId) {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let nots = circuit.create_nodes(NodeType::Not, 3);
+        let or = circuit.create_node(NodeType::Or);
+        for i in order {
+            circuit.connect(input, nots[i]);
+        }
+        for i in order {
+            circuit.connect(nots[i], or);
+        }
+        circuit.run_until_done();
+        (circuit, input)
+    }
+
+    /// The nodes that changed on each tick, in the order they changed.
+    fn changes(circuit: &mut Circuit, input: NodeId) -> Vec<Vec<NodeId>> {
+        circuit.set_input(input, true);
+        let mut changes = Vec::new();
+        while circuit.work_left() {
+            circuit.update();
+            // Changed nodes get queued at the front
+            let mut changed = circuit.update_queue();
+            changed.reverse();
+            changes.push(changed);
+        }
+        changes
+    }
+
+    #[test]
+    fn stable_order_test() {
+        let (mut forward, forward_input) = fan_out_in([0, 1, 2]);
+        let (mut backward, backward_input) = fan_out_in([2, 1, 0]);
+        let mut forward_clone = forward.clone();
+        let mut backward_clone = backward.clone();
+        assert_ne!(
+            changes(&mut forward_clone, forward_input),
+            changes(&mut backward_clone, backward_input)
+        );
+
+        forward.set_stable_order(true);
+        backward.set_stable_order(true);
+        let forward_changes = changes(&mut forward, forward_input);
+        assert_eq!(forward_changes[0].len(), 3);
+        assert!(forward_changes[0].windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(forward_changes, changes(&mut backward, backward_input));
+        assert_eq!(
+            forward
+                .node_ids()
+                .map(|node_id| forward.get_output(node_id))
+                .collect::<Vec<_>>(),
+            backward
+                .node_ids()
+                .map(|node_id| backward.get_output(node_id))
+                .collect::<Vec<_>>()
+        );
+    }
+}
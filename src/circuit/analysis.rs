@@ -0,0 +1,318 @@
+use super::{Circuit, GateType, NodeId};
+
+/// A feedback loop found by [Circuit::classify_loops], with the nodes in it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoopKind {
+    /// Every path around the loop goes through an even number of inverting
+    /// gates, so it can hold a stable value like a latch does.
+    Latch(Vec<NodeId>),
+    /// Some path around the loop goes through an odd number of inverting
+    /// gates, or through an XOR whose polarity depends on its other inputs,
+    /// so it may oscillate.
+    Oscillator(Vec<NodeId>),
+}
+
+impl Circuit {
+    /// Strongly connected components of the circuit that contain a cycle,
+    /// i.e. more than one node or a node connected to itself. These are
+    /// where the latches, counters and oscillators are, while every node
+    /// outside them is combinational. See [Self::classify_loops] to tell
+    /// them apart.
+    pub fn feedback_components(&self) -> Vec<Vec<NodeId>> {
+        const UNVISITED: u32 = u32::MAX;
+        let num_nodes = self.node_data.len();
+        let mut index = vec![UNVISITED; num_nodes];
+        let mut low_link = vec![0; num_nodes];
+        let mut on_stack = vec![false; num_nodes];
+        let mut stack = Vec::new();
+        let mut next_index = 0;
+        let mut loops = Vec::new();
+
+        // Iterative Tarjan, since recursing could overflow on long chains
+        for root in self.node_ids() {
+            if index[root] != UNVISITED {
+                continue;
+            }
+            let mut call_stack = vec![(root, 0)];
+            while let Some((node_id, child_idx)) = call_stack.pop() {
+                if child_idx == 0 {
+                    index[node_id] = next_index;
+                    low_link[node_id] = next_index;
+                    next_index += 1;
+                    stack.push(node_id);
+                    on_stack[node_id] = true;
+                }
+                if let Some(child) = self.node_children[node_id].get(child_idx).cloned() {
+                    call_stack.push((node_id, child_idx + 1));
+                    if index[child] == UNVISITED {
+                        call_stack.push((child, 0));
+                    } else if on_stack[child] {
+                        low_link[node_id] = low_link[node_id].min(index[child]);
+                    }
+                    continue;
+                }
+
+                if let Some((parent, _)) = call_stack.last().cloned() {
+                    low_link[parent] = low_link[parent].min(low_link[node_id]);
+                }
+                if low_link[node_id] == index[node_id] {
+                    let mut component = Vec::new();
+                    loop {
+                        let member = stack.pop().unwrap();
+                        on_stack[member] = false;
+                        component.push(member);
+                        if member == node_id {
+                            break;
+                        }
+                    }
+                    if component.len() > 1 || self.node_children[node_id].contains(&node_id) {
+                        component.reverse();
+                        loops.push(component);
+                    }
+                }
+            }
+        }
+        loops
+    }
+
+    /// Every node in an order where each node comes before its children,
+    /// except along edges that close a feedback loop. Loops are cut where
+    /// a depth-first search from the nodes without parents first enters
+    /// them.
+    pub(crate) fn topological_order(&self) -> Vec<NodeId> {
+        let parents = self.node_parents();
+        let roots = self
+            .node_ids()
+            .filter(|node_id| parents[*node_id].is_empty())
+            .chain(self.node_ids());
+
+        let mut visited = vec![false; self.node_data.len()];
+        let mut post_order = Vec::with_capacity(self.node_data.len());
+        for root in roots {
+            if visited[root] {
+                continue;
+            }
+            visited[root] = true;
+            let mut call_stack = vec![(root, 0)];
+            while let Some((node_id, child_idx)) = call_stack.pop() {
+                if let Some(child) = self.node_children[node_id].get(child_idx).cloned() {
+                    call_stack.push((node_id, child_idx + 1));
+                    if !visited[child] {
+                        visited[child] = true;
+                        call_stack.push((child, 0));
+                    }
+                } else {
+                    post_order.push(node_id);
+                }
+            }
+        }
+        post_order.reverse();
+        post_order
+    }
+
+    /// The logic level of each node, indexed by [NodeId]: the length of the
+    /// longest path reaching it from a node without parents, which are at
+    /// level 0. Paths don't go around feedback loops, which are cut as in
+    /// [Self::topological_order]. Levels aren't kept up to date as the
+    /// circuit changes, so callers should hold on to the result.
+    pub fn levels(&self) -> Vec<u32> {
+        let order = self.topological_order();
+        let mut position = vec![0; self.node_data.len()];
+        for (i, node_id) in order.iter().cloned().enumerate() {
+            position[node_id] = i;
+        }
+
+        let mut levels = vec![0; self.node_data.len()];
+        for node_id in order {
+            for child in self.node_children[node_id].iter().cloned() {
+                if position[child] > position[node_id] {
+                    levels[child] = levels[child].max(levels[node_id] + 1);
+                }
+            }
+        }
+        levels
+    }
+
+    /// Whether the output of the node is the inverse of its inputs, or
+    /// [None] if that depends on the other inputs.
+    pub(super) fn inverts(&self, node_id: NodeId) -> Option<bool> {
+        let node_data = &self.node_data[node_id];
+        match node_data.gate_type {
+            GateType::OrNor => Some(node_data.inverted),
+            GateType::AndNand => Some(!node_data.inverted),
+            GateType::XorXnor => None,
+            GateType::Const => unreachable!("Constant nodes have no inputs"),
+        }
+    }
+
+    /// Finds every feedback loop in the circuit and classifies it by the
+    /// number of inversions around it. This is a purely structural check,
+    /// so it's meant as a design-time sanity check of latches rather than
+    /// a proof that a loop will or won't oscillate.
+    pub fn classify_loops(&self) -> Vec<LoopKind> {
+        let mut polarity = vec![None; self.node_data.len()];
+        // Shared between components, only the current one is ever set
+        let mut in_loop = vec![false; self.node_data.len()];
+        self.feedback_components()
+            .into_iter()
+            .map(|component| {
+                for node_id in component.iter().cloned() {
+                    in_loop[node_id] = true;
+                }
+                // Loops are even iff each node can be given a consistent
+                // polarity relative to the first one
+                let mut even = true;
+                polarity[component[0]] = Some(false);
+                let mut stack = vec![component[0]];
+                while let Some(node_id) = stack.pop() {
+                    let node_polarity = polarity[node_id].unwrap();
+                    for child in self.node_children[node_id].iter().cloned() {
+                        if !in_loop[child] {
+                            continue;
+                        }
+                        let Some(inverts) = self.inverts(child) else {
+                            even = false;
+                            continue;
+                        };
+                        match polarity[child] {
+                            None => {
+                                polarity[child] = Some(node_polarity ^ inverts);
+                                stack.push(child);
+                            }
+                            Some(child_polarity) => {
+                                even &= child_polarity == node_polarity ^ inverts;
+                            }
+                        }
+                    }
+                }
+                for node_id in component.iter().cloned() {
+                    in_loop[node_id] = false;
+                }
+                if even {
+                    LoopKind::Latch(component)
+                } else {
+                    LoopKind::Oscillator(component)
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, sync::Arc};
+
+    use crate::{
+        circuit_builder::{CircuitBuilder, Connector},
+        circuit_sim::{CircuitSim, NodeType},
+        components::adder::RippleCarryAdder,
+        Circuit,
+    };
+
+    use super::LoopKind;
+
+    #[test]
+    fn classify_loops_test() {
+        let mut circuit = Circuit::new();
+
+        let ring: Vec<_> = (0..3).map(|_| circuit.create_node(NodeType::Nor)).collect();
+        for i in 0..ring.len() {
+            circuit.connect(ring[i], ring[(i + 1) % ring.len()]);
+        }
+
+        let set = circuit.create_input();
+        let reset = circuit.create_input();
+        let q = circuit.create_node(NodeType::Nor);
+        let q_not = circuit.create_node(NodeType::Nor);
+        circuit.connect(reset, q);
+        circuit.connect(q_not, q);
+        circuit.connect(set, q_not);
+        circuit.connect(q, q_not);
+
+        let a = circuit.create_input();
+        let b = circuit.create_node(NodeType::And);
+        circuit.connect(a, b);
+
+        let mut loops = circuit.classify_loops();
+        loops.sort_by_key(|kind| match kind {
+            LoopKind::Latch(nodes) | LoopKind::Oscillator(nodes) => nodes.len(),
+        });
+        assert_eq!(loops.len(), 2);
+        match &loops[0] {
+            LoopKind::Latch(nodes) => {
+                let mut nodes = nodes.clone();
+                nodes.sort();
+                assert_eq!(nodes, vec![q, q_not]);
+            }
+            kind => panic!("SR latch classified as {kind:?}"),
+        }
+        match &loops[1] {
+            LoopKind::Oscillator(nodes) => {
+                let mut nodes = nodes.clone();
+                nodes.sort();
+                assert_eq!(nodes, ring);
+            }
+            kind => panic!("Inverter ring classified as {kind:?}"),
+        }
+    }
+
+    #[test]
+    fn feedback_components_test() {
+        let mut circuit = Circuit::new();
+        let set = circuit.create_input();
+        let reset = circuit.create_input();
+        let enable = circuit.create_input();
+        let gated_set = circuit.create_node(NodeType::And);
+        circuit.connect(set, gated_set);
+        circuit.connect(enable, gated_set);
+        let gated_reset = circuit.create_node(NodeType::And);
+        circuit.connect(reset, gated_reset);
+        circuit.connect(enable, gated_reset);
+
+        let q = circuit.create_node(NodeType::Nor);
+        let q_not = circuit.create_node(NodeType::Nor);
+        circuit.connect(gated_reset, q);
+        circuit.connect(q_not, q);
+        circuit.connect(gated_set, q_not);
+        circuit.connect(q, q_not);
+
+        let output = circuit.create_node(NodeType::Xor);
+        circuit.connect(q, output);
+        circuit.connect(enable, output);
+
+        let mut components = circuit.feedback_components();
+        assert_eq!(components.len(), 1);
+        components[0].sort();
+        assert_eq!(components[0], [q, q_not]);
+    }
+
+    #[test]
+    fn levels_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let (cin, _) = Connector::input(builder.clone());
+        let rca = RippleCarryAdder::<4>::new(builder.clone(), cin);
+        let mut borrow = builder.borrow_mut();
+        let (circuit, _) = borrow.build();
+
+        let levels = circuit.levels();
+        for input in rca.input_a.iter().chain(rca.input_b.iter()) {
+            assert_eq!(levels[*input], 0);
+        }
+        // Each bit waits two gates for the carry, then one for the XOR
+        for (i, sum) in rca.sum.iter().enumerate() {
+            assert_eq!(levels[*sum], 2 * i as u32 + 1);
+        }
+        assert_eq!(levels[rca.cout], 8);
+
+        let mut circuit = Circuit::new();
+        let set = circuit.create_input();
+        let q = circuit.create_node(NodeType::Nor);
+        let q_not = circuit.create_node(NodeType::Nor);
+        circuit.connect(set, q_not);
+        circuit.connect(q, q_not);
+        circuit.connect(q_not, q);
+        let levels = circuit.levels();
+        assert_eq!(levels[q_not], 1);
+        assert_eq!(levels[q], 2);
+    }
+}
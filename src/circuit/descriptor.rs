@@ -0,0 +1,308 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    sync::atomic::AtomicU32,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{
+    hierarchy::Hierarchy, Circuit, CircuitSnapshot, GateType, NodeData, NodeId, NodeIdBuilder,
+    UpdateData,
+};
+use crate::circuit_sim::{Tick, Ticks};
+
+/// Everything needed to rebuild a [Circuit], made by [Circuit::descriptor].
+/// With the `serde` feature it can be serialized, for saving designs.
+/// Besides the connections it holds the whole simulation state, so the
+/// rebuilt circuit carries on exactly where the original was, and keeps
+/// the same [NodeId]s. Probes, change tracking and stability tracking
+/// aren't kept.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CircuitDescriptor {
+    // Indexed by [NodeId], including deleted ones
+    nodes: Vec<NodeDescriptor>,
+    // Deleted ids, waiting to be reused or still in a queue
+    unused: Vec<NodeId>,
+    dying: Vec<NodeId>,
+    clock_periods: Vec<(NodeId, Ticks)>,
+    stable_order: bool,
+    phases: Vec<(Tick, String)>,
+    hierarchy: Hierarchy,
+    node_names: Vec<(NodeId, String)>,
+    state: CircuitSnapshot,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct NodeDescriptor {
+    gate_type: GateType,
+    inverted: bool,
+    children: Vec<NodeId>,
+}
+
+/// Why [Circuit::from_descriptor] rejected a descriptor, which can only
+/// happen when it wasn't made by [Circuit::descriptor], like a hand-edited
+/// or corrupted file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DescriptorError {
+    /// A node id that's past the last node.
+    NodeIdOutOfRange(NodeId),
+    /// The parts of the descriptor don't agree with each other.
+    Inconsistent(String),
+}
+
+impl Display for DescriptorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DescriptorError::NodeIdOutOfRange(node_id) => {
+                write!(f, "Node id {node_id:?} is out of range")
+            }
+            DescriptorError::Inconsistent(message) => write!(f, "Inconsistent circuit: {message}"),
+        }
+    }
+}
+
+impl Error for DescriptorError {}
+
+fn inconsistent<T>(message: impl Into<String>) -> Result<T, DescriptorError> {
+    Err(DescriptorError::Inconsistent(message.into()))
+}
+
+/// Fails for ids past `num_nodes`, which would index out of bounds.
+pub(super) fn check_node_id(node_id: NodeId, num_nodes: usize) -> Result<(), DescriptorError> {
+    if (node_id.0 as usize) < num_nodes {
+        Ok(())
+    } else {
+        Err(DescriptorError::NodeIdOutOfRange(node_id))
+    }
+}
+
+/// Fails for out of range ids, or an id queued twice, which would link the
+/// queue into a loop.
+fn check_queue(
+    queue: impl Iterator<Item = NodeId>,
+    num_nodes: usize,
+    name: &str,
+) -> Result<(), DescriptorError> {
+    let mut seen = HashSet::new();
+    for node_id in queue {
+        check_node_id(node_id, num_nodes)?;
+        if !seen.insert(node_id) {
+            return inconsistent(format!("{node_id:?} is in the {name} queue twice"));
+        }
+    }
+    Ok(())
+}
+
+impl CircuitSnapshot {
+    /// Checks the snapshot fits a circuit of `num_nodes` nodes and
+    /// `num_clocks` clocks.
+    pub(super) fn check(&self, num_nodes: usize, num_clocks: usize) -> Result<(), DescriptorError> {
+        if self.nodes.len() != num_nodes || self.clock_toggles.len() != num_clocks {
+            return inconsistent("Snapshot is of a different circuit");
+        }
+        check_queue(self.update_queue.iter().cloned(), num_nodes, "update")?;
+        check_queue(
+            self.changed_queue.iter().map(|(node_id, _)| *node_id),
+            num_nodes,
+            "changed",
+        )?;
+        for (_, input, _) in self.held.iter() {
+            check_node_id(*input, num_nodes)?;
+        }
+        Ok(())
+    }
+}
+
+impl CircuitDescriptor {
+    fn check(&self) -> Result<(), DescriptorError> {
+        let num_nodes = self.nodes.len();
+        if num_nodes >= NodeId::NULL.0 as usize {
+            return inconsistent(format!("{num_nodes} nodes is more than ids can address"));
+        }
+        for node in self.nodes.iter() {
+            for child in node.children.iter().cloned() {
+                check_node_id(child, num_nodes)?;
+                if self.nodes[child.0 as usize].gate_type == GateType::Const {
+                    return inconsistent(format!("Constant node {child:?} has inputs"));
+                }
+            }
+        }
+        let clocks = self.clock_periods.iter().map(|(clock, _)| clock);
+        let named = self.node_names.iter().map(|(node_id, _)| node_id);
+        for node_id in self
+            .unused
+            .iter()
+            .chain(self.dying.iter())
+            .chain(clocks)
+            .chain(named)
+        {
+            check_node_id(*node_id, num_nodes)?;
+        }
+        self.hierarchy.check(num_nodes)?;
+        self.state.check(num_nodes, self.clock_periods.len())
+    }
+}
+
+impl Circuit {
+    pub fn descriptor(&self) -> CircuitDescriptor {
+        let nodes = self
+            .node_data
+            .iter()
+            .zip(self.node_children.iter())
+            .map(|(node_data, children)| NodeDescriptor {
+                gate_type: node_data.gate_type,
+                inverted: node_data.inverted,
+                children: children.clone(),
+            })
+            .collect();
+        CircuitDescriptor {
+            nodes,
+            unused: self.node_id_builder.unused.iter().cloned().collect(),
+            dying: self.dying.clone(),
+            clock_periods: self
+                .clocks
+                .iter()
+                .map(|(clock, period, _)| (*clock, *period))
+                .collect(),
+            stable_order: self.stable_order,
+            phases: self.phases.clone(),
+            hierarchy: self.hierarchy.clone(),
+            node_names: self
+                .node_names
+                .iter()
+                .map(|(node_id, name)| (*node_id, name.clone()))
+                .collect(),
+            state: self.snapshot(),
+        }
+    }
+
+    /// Rebuilds the circuit described by [Circuit::descriptor]. Descriptors
+    /// loaded from elsewhere are checked first, so a bad one gives an error
+    /// rather than a broken circuit.
+    pub fn from_descriptor(descriptor: &CircuitDescriptor) -> Result<Circuit, DescriptorError> {
+        descriptor.check()?;
+        let num_nodes = descriptor.nodes.len();
+        let mut circuit = Circuit {
+            node_id_builder: NodeIdBuilder {
+                next: AtomicU32::new(num_nodes as u32),
+                unused: descriptor.unused.iter().cloned().collect(),
+            },
+            node_children: Vec::with_capacity(num_nodes),
+            node_data: Vec::with_capacity(num_nodes),
+            node_update_data: vec![UpdateData::default(); num_nodes],
+            dying: descriptor.dying.clone(),
+            clocks: descriptor
+                .clock_periods
+                .iter()
+                .map(|(clock, period)| (*clock, *period, 0))
+                .collect(),
+            stable_order: descriptor.stable_order,
+            phases: descriptor.phases.clone(),
+            hierarchy: descriptor.hierarchy.clone(),
+            node_names: descriptor.node_names.iter().cloned().collect(),
+            ..Circuit::default()
+        };
+        for node in descriptor.nodes.iter() {
+            circuit.node_data.push(NodeData {
+                gate_type: node.gate_type,
+                inverted: node.inverted,
+                ..NodeData::default()
+            });
+            circuit.node_children.push(node.children.clone());
+        }
+        circuit.restore(&descriptor.state);
+        Ok(circuit)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        circuit::NodeId,
+        circuit_sim::{CircuitSim, NodeType},
+        Circuit,
+    };
+
+    use super::{CircuitDescriptor, DescriptorError};
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn sram_round_trip_test() {
+        use crate::components::memory::Sram;
+
+        let mut circuit = Circuit::default();
+        let sram = Sram::new::<16>(&mut circuit);
+        sram.set(&mut circuit, 5, 0xBEEF);
+        sram.set(&mut circuit, 9, 0x1234);
+
+        let json = serde_json::to_string(&circuit.descriptor()).unwrap();
+        let descriptor: CircuitDescriptor = serde_json::from_str(&json).unwrap();
+        let mut loaded = Circuit::from_descriptor(&descriptor).unwrap();
+        assert_eq!(format!("{loaded:?}"), format!("{circuit:?}"));
+        for (address, val) in [(5, 0xBEEF), (9, 0x1234)] {
+            assert_eq!(sram.get(&mut loaded, address), val);
+            assert_eq!(sram.get(&mut circuit, address), val);
+        }
+
+        // Both carry on exactly the same
+        sram.set(&mut loaded, 5, 0x4321);
+        sram.set(&mut circuit, 5, 0x4321);
+        assert_eq!(loaded.tick(), circuit.tick());
+        assert_eq!(sram.get(&mut loaded, 5), sram.get(&mut circuit, 5));
+    }
+
+    #[test]
+    fn bad_descriptor_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let not = circuit.create_node(NodeType::Not);
+        circuit.connect(input, not);
+        circuit.clock(2);
+        circuit.set_input(input, true);
+        circuit.set_node_name(not, "not");
+        let descriptor = circuit.descriptor();
+        let loaded = Circuit::from_descriptor(&descriptor).unwrap();
+        assert_eq!(loaded.node_name(not), Some("not"));
+
+        let out_of_range = NodeId(3);
+        let broken: [fn(&mut CircuitDescriptor); 6] = [
+            |descriptor| descriptor.nodes[0].children.push(NodeId(3)),
+            |descriptor| descriptor.unused.push(NodeId(3)),
+            |descriptor| descriptor.clock_periods[0].0 = NodeId(3),
+            |descriptor| descriptor.state.update_queue.push(NodeId(3)),
+            |descriptor| descriptor.state.held.push((10, NodeId(3), false)),
+            |descriptor| descriptor.node_names[0].0 = NodeId(3),
+        ];
+        for (i, breaks) in broken.iter().enumerate() {
+            let mut bad = descriptor.clone();
+            breaks(&mut bad);
+            let err = Circuit::from_descriptor(&bad).unwrap_err();
+            assert_eq!(err, DescriptorError::NodeIdOutOfRange(out_of_range), "{i}");
+        }
+
+        let inconsistent: [fn(&mut CircuitDescriptor); 3] = [
+            |descriptor| {
+                let queued = descriptor.state.changed_queue[0];
+                descriptor.state.changed_queue.push(queued);
+            },
+            |descriptor| {
+                descriptor.state.nodes.pop();
+            },
+            |descriptor| descriptor.nodes[1].gate_type = super::GateType::Const,
+        ];
+        for (i, breaks) in inconsistent.iter().enumerate() {
+            let mut bad = descriptor.clone();
+            breaks(&mut bad);
+            let err = Circuit::from_descriptor(&bad).unwrap_err();
+            assert!(
+                matches!(err, DescriptorError::Inconsistent(_)),
+                "{i}: {err}"
+            );
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::fmt::Write;
+
+use super::{Circuit, NodeId};
+
+impl Circuit {
+    /// The circuit as a Graphviz `digraph`, with an edge for every
+    /// connection. Nodes are labeled with their name from
+    /// [Circuit::set_node_name], or their id when unnamed, and their
+    /// [crate::circuit_sim::NodeType], and filled in when their output is
+    /// high, so rendering it shows which wires are active. Nodes created in
+    /// a module from [Circuit::begin_module] are drawn inside a cluster
+    /// named after it.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph circuit {\n    node [style=filled];\n");
+        let mut module_nodes = vec![Vec::new(); self.hierarchy.modules.len()];
+        for node_id in self.node_ids() {
+            match self.hierarchy.module_of(node_id) {
+                Some(module) => module_nodes[module].push(node_id),
+                None => self.write_dot_node(&mut dot, node_id, 1),
+            }
+        }
+        for module in self.hierarchy.children(None) {
+            self.write_dot_cluster(&mut dot, module, &module_nodes, 1);
+        }
+        for (input, output) in self.edges() {
+            writeln!(dot, "    n{} -> n{};", input.0, output.0).unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn write_dot_node(&self, dot: &mut String, node_id: NodeId, depth: usize) {
+        let node_data = &self.node_data[node_id];
+        let gate = self.node_kind(node_id);
+        let name = match self.node_name(node_id) {
+            Some(name) => name.replace('"', "\\\""),
+            None => node_id.0.to_string(),
+        };
+        let color = if node_data.output {
+            "palegreen"
+        } else {
+            "white"
+        };
+        writeln!(
+            dot,
+            "{:indent$}n{} [label=\"{name}: {gate:?}\", fillcolor={color}];",
+            "",
+            node_id.0,
+            indent = depth * 4
+        )
+        .unwrap();
+    }
+
+    fn write_dot_cluster(
+        &self,
+        dot: &mut String,
+        module: usize,
+        module_nodes: &[Vec<NodeId>],
+        depth: usize,
+    ) {
+        let modules = &self.hierarchy.modules;
+        let cluster_id = |name: &str| -> String {
+            name.chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect()
+        };
+        let name = &modules[module].0;
+        let mut id = cluster_id(name);
+        // Graphviz merges subgraphs with the same name, so only the first
+        // module with a name gets it as is
+        if modules[..module]
+            .iter()
+            .any(|(other, _)| cluster_id(other) == id)
+        {
+            write!(id, "_{module}").unwrap();
+        }
+        let indent = depth * 4;
+        writeln!(dot, "{:indent$}subgraph cluster_{id} {{", "").unwrap();
+        writeln!(
+            dot,
+            "{:indent$}    label=\"{}\";",
+            "",
+            name.replace('"', "\\\"")
+        )
+        .unwrap();
+        for node_id in module_nodes[module].iter() {
+            self.write_dot_node(dot, *node_id, depth + 1);
+        }
+        for child in self.hierarchy.children(Some(module)) {
+            self.write_dot_cluster(dot, child, module_nodes, depth + 1);
+        }
+        writeln!(dot, "{:indent$}}}", "").unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, sync::Arc};
+
+    use crate::{
+        circuit_builder::{CircuitBuilder, CircuitBuilderWithHooks, Connector, Names},
+        circuit_sim::{CircuitSim, NodeType},
+        components::adder::RippleCarryAdder,
+        Circuit,
+    };
+
+    #[test]
+    fn full_adder_dot_test() {
+        let mut circuit = Circuit::new();
+        let a = circuit.create_input();
+        let b = circuit.create_input();
+        let cin = circuit.create_input();
+        let half_sum = circuit.create_node(NodeType::Xor);
+        let sum = circuit.create_node(NodeType::Xor);
+        let half_carry = circuit.create_node(NodeType::And);
+        let carry = circuit.create_node(NodeType::And);
+        let cout = circuit.create_node(NodeType::Or);
+        let connections = [
+            (a, half_sum),
+            (b, half_sum),
+            (half_sum, sum),
+            (cin, sum),
+            (a, half_carry),
+            (b, half_carry),
+            (half_sum, carry),
+            (cin, carry),
+            (half_carry, cout),
+            (carry, cout),
+        ];
+        for (input, output) in connections {
+            circuit.connect(input, output);
+        }
+        circuit.set_input(a, true);
+        circuit.run_until_done();
+
+        let dot = circuit.to_dot();
+        assert!(dot.starts_with("digraph"));
+        assert_eq!(dot.matches(" -> ").count(), connections.len());
+        assert_eq!(dot.matches("[label=").count(), circuit.num_nodes());
+        assert_eq!(dot.matches("fillcolor=palegreen").count(), 3);
+        assert!(dot.contains(&format!("n{} -> n{};", a.0, half_sum.0)));
+        assert!(dot.contains(&format!("[label=\"{}: And\"", carry.0)));
+        assert!(dot.contains(&format!("[label=\"{}: Xor\"", sum.0)));
+    }
+
+    #[test]
+    fn marked_names_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilderWithHooks::<Names>::default()));
+        let (a, _) = Connector::input(builder.clone());
+        let b = a.invert();
+        b.mark("carry_out".to_string());
+        let mut borrow = builder.borrow_mut();
+        let circuit = borrow.build_with_names();
+        assert_eq!(circuit.node_name(b.output), Some("carry_out"));
+        assert_eq!(circuit.node_name(a.output), None);
+
+        let dot = circuit.to_dot();
+        assert!(dot.contains(&format!("n{} [label=\"carry_out: Nor\"", b.output.0)));
+        assert!(dot.contains(&format!("n{} [label=\"{}: Or\"", a.output.0, a.output.0)));
+    }
+
+    #[test]
+    fn module_cluster_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let outside = Connector::input_ignore(builder.clone());
+        builder.borrow_mut().begin_module("adder");
+        let adder = RippleCarryAdder::<2>::new(
+            builder.clone(),
+            Connector::constant(builder.clone(), false),
+        );
+        builder.borrow_mut().end_module();
+        let after = Connector::input_ignore(builder.clone());
+
+        let mut borrow = builder.borrow_mut();
+        let (circuit, _) = borrow.build();
+        let dot = circuit.to_dot();
+        let start = dot.find("    subgraph cluster_adder {\n").unwrap();
+        let end = start + dot[start..].find("\n    }\n").unwrap();
+        let cluster = &dot[start..end];
+        assert!(cluster.contains("label=\"adder\";"));
+        assert!(cluster.contains(&format!("n{} [", adder.input_a[0].0)));
+        assert!(cluster.contains(&format!("n{} [", adder.sum[1].0)));
+        assert!(!cluster.contains(&format!("n{} [", outside.output.0)));
+        assert!(!cluster.contains(&format!("n{} [", after.output.0)));
+        // The adder's own module is nested inside
+        assert!(cluster.contains("        subgraph cluster_ripple_carry_adder {\n"));
+        let in_adder = circuit
+            .node_ids()
+            .filter(|node_id| circuit.module_path(*node_id).first() == Some(&"adder"))
+            .count();
+        assert_eq!(cluster.matches("[label=").count(), in_adder);
+    }
+}
@@ -0,0 +1,146 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{
+    descriptor::{check_node_id, DescriptorError},
+    Circuit, NodeId,
+};
+
+/// The modules from [Circuit::begin_module], so exporters can group the
+/// gates of each component instead of flattening them.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(super) struct Hierarchy {
+    // Name and enclosing module of every module begun so far
+    pub(super) modules: Vec<(String, Option<usize>)>,
+    // Modules begun but not yet ended, innermost last
+    open: Vec<usize>,
+    // The innermost module each node was created in, if any, indexed by
+    // [NodeId] and only as long as the last node created in a module
+    node_modules: Vec<Option<usize>>,
+}
+
+impl Hierarchy {
+    pub(super) fn node_created(&mut self, node_id: NodeId) {
+        let module = self.open.last().cloned();
+        let index = node_id.0 as usize;
+        if index >= self.node_modules.len() {
+            if module.is_none() {
+                return;
+            }
+            self.node_modules.resize(index + 1, None);
+        }
+        self.node_modules[index] = module;
+    }
+
+    pub(super) fn node_deleted(&mut self, node_id: NodeId) {
+        if let Some(module) = self.node_modules.get_mut(node_id.0 as usize) {
+            *module = None;
+        }
+    }
+
+    /// The innermost module the node was created in, if any.
+    pub(super) fn module_of(&self, node_id: NodeId) -> Option<usize> {
+        self.node_modules.get(node_id.0 as usize).cloned().flatten()
+    }
+
+    /// Checks every index is in range, for [Circuit::from_descriptor].
+    /// Modules can only be inside ones begun before them, so following
+    /// parents can't loop.
+    pub(super) fn check(&self, num_nodes: usize) -> Result<(), DescriptorError> {
+        let bad_module = |module: usize| {
+            DescriptorError::Inconsistent(format!("Module {module} is out of range"))
+        };
+        for (module, (_, parent)) in self.modules.iter().enumerate() {
+            if parent.is_some_and(|parent| parent >= module) {
+                return Err(bad_module(parent.unwrap()));
+            }
+        }
+        let node_modules = self.node_modules.iter().flatten();
+        for module in self.open.iter().chain(node_modules).cloned() {
+            if module >= self.modules.len() {
+                return Err(bad_module(module));
+            }
+        }
+        if self.node_modules.len() > num_nodes {
+            check_node_id(NodeId(self.node_modules.len() as u32 - 1), num_nodes)?;
+        }
+        Ok(())
+    }
+
+    /// The modules directly inside `parent`, or the outermost ones for
+    /// [None], in the order they were begun.
+    pub(super) fn children(&self, parent: Option<usize>) -> impl Iterator<Item = usize> + '_ {
+        self.modules
+            .iter()
+            .enumerate()
+            .filter(move |(_, (_, module_parent))| *module_parent == parent)
+            .map(|(module, _)| module)
+    }
+}
+
+impl Circuit {
+    /// Groups every node created from now until the matching
+    /// [Self::end_module] under `name`, like a component would. Modules
+    /// nest, and don't change how the circuit runs, only how exporters like
+    /// [Self::to_dot] lay it out.
+    pub fn begin_module(&mut self, name: &str) {
+        let parent = self.hierarchy.open.last().cloned();
+        self.hierarchy.modules.push((name.to_string(), parent));
+        self.hierarchy.open.push(self.hierarchy.modules.len() - 1);
+    }
+
+    /// Ends the innermost module begun with [Self::begin_module].
+    pub fn end_module(&mut self) {
+        self.hierarchy
+            .open
+            .pop()
+            .expect("No module to end, begin_module wasn't called");
+    }
+
+    /// The names of the modules the node was created in, outermost first,
+    /// or empty if it wasn't created in any.
+    pub fn module_path(&self, node_id: NodeId) -> Vec<&str> {
+        let mut path = Vec::new();
+        let mut module = self.hierarchy.module_of(node_id);
+        while let Some(index) = module {
+            let (name, parent) = &self.hierarchy.modules[index];
+            path.push(name.as_str());
+            module = *parent;
+        }
+        path.reverse();
+        path
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        circuit_sim::{CircuitSim, NodeType},
+        Circuit,
+    };
+
+    #[test]
+    fn module_path_test() {
+        let mut circuit = Circuit::new();
+        let outside = circuit.create_input();
+        circuit.begin_module("cpu");
+        let cpu_node = circuit.create_node(NodeType::And);
+        circuit.begin_module("alu");
+        let alu_nodes = circuit.create_nodes(NodeType::Xor, 2);
+        circuit.end_module();
+        circuit.end_module();
+        let after = circuit.create_node(NodeType::Or);
+
+        assert!(circuit.module_path(outside).is_empty());
+        assert_eq!(circuit.module_path(cpu_node), ["cpu"]);
+        assert_eq!(circuit.module_path(alu_nodes[1]), ["cpu", "alu"]);
+        assert!(circuit.module_path(after).is_empty());
+
+        // Recycled ids forget the module of the deleted node
+        circuit.delete_node(cpu_node);
+        let recycled = circuit.create_node(NodeType::Or);
+        assert_eq!(recycled, cpu_node);
+        assert!(circuit.module_path(recycled).is_empty());
+    }
+}
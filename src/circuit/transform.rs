@@ -0,0 +1,639 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use super::{Circuit, GateType, NodeData, NodeId};
+use crate::circuit_sim::{CircuitSim, NodeType};
+use crate::InputId;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtractError {
+    /// The cone of an output reaches a node without parents that isn't one of
+    /// the given inputs, so the extracted circuit would be missing a driver.
+    UnboundedCone(NodeId),
+}
+
+impl Display for ExtractError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractError::UnboundedCone(node_id) => write!(
+                f,
+                "Cone of logic reaches {node_id:?}, which is outside of the given inputs"
+            ),
+        }
+    }
+}
+
+impl Error for ExtractError {}
+
+/// A standalone copy of part of a [Circuit], made by [Circuit::extract].
+/// `inputs` and `outputs` line up with the ports passed to `extract`.
+#[derive(Debug)]
+pub struct Extracted {
+    pub circuit: Circuit,
+    pub inputs: Vec<InputId>,
+    pub outputs: Vec<NodeId>,
+}
+
+/// Builds a circuit out of a single kind of universal gate, for
+/// [Circuit::to_nand_only] and [Circuit::to_nor_only].
+struct UniversalBuilder {
+    circuit: Circuit,
+    nor: bool,
+}
+
+impl UniversalBuilder {
+    fn gate(&mut self, inputs: &[NodeId]) -> NodeId {
+        let gate = self.circuit.add_node(Self::gate_type(self.nor), self.nor);
+        self.set_inputs(gate, inputs);
+        gate
+    }
+
+    fn gate_type(nor: bool) -> GateType {
+        if nor {
+            GateType::OrNor
+        } else {
+            GateType::AndNand
+        }
+    }
+
+    /// Connects `inputs` into an existing gate and updates its output.
+    fn set_inputs(&mut self, gate: NodeId, inputs: &[NodeId]) {
+        let circuit = &mut self.circuit;
+        for input in inputs.iter().cloned() {
+            circuit.node_children[input].push(gate);
+        }
+        let mut outputs = inputs.iter().map(|input| circuit.node_data[*input].output);
+        // NAND is false only if all inputs are true, NOR only if any is
+        let output = if self.nor {
+            !outputs.any(|output| output)
+        } else {
+            !outputs.all(|output| output)
+        };
+        circuit.node_data[gate].output = output;
+    }
+
+    /// Two gates in a row, which undo each other's inversion.
+    fn buffer(&mut self, input: NodeId, output: NodeId) {
+        let inverted = self.gate(&[input]);
+        self.set_inputs(output, &[inverted]);
+    }
+
+    /// XOR of two inputs out of four gates, or XNOR when using NOR gates.
+    fn xor2(&mut self, a: NodeId, b: NodeId, output: NodeId) {
+        let both = self.gate(&[a, b]);
+        let a = self.gate(&[a, both]);
+        let b = self.gate(&[b, both]);
+        self.set_inputs(output, &[a, b]);
+    }
+
+    /// Rewrites a gate of `node_type` to drive `output` from `inputs`.
+    fn rewrite(&mut self, node_type: NodeType, inputs: &[NodeId], output: NodeId) {
+        use NodeType::*;
+        let node_type = match node_type {
+            Buffer => Or,
+            Not => Nor,
+            node_type => node_type,
+        };
+        // Recipes are written for NAND gates. Built out of NOR gates they
+        // compute the dual function instead, so look up the dual's recipe
+        let recipe = match node_type {
+            Or if self.nor => And,
+            Nor if self.nor => Nand,
+            And if self.nor => Or,
+            Nand if self.nor => Nor,
+            node_type => node_type,
+        };
+        match recipe {
+            Nand => self.set_inputs(output, inputs),
+            And => {
+                let nand = self.gate(inputs);
+                self.set_inputs(output, &[nand]);
+            }
+            Or | Nor => {
+                let inverted: Vec<_> = inputs.iter().map(|input| self.gate(&[*input])).collect();
+                if recipe == Or {
+                    self.set_inputs(output, &inverted);
+                } else {
+                    let or = self.gate(&inverted);
+                    self.set_inputs(output, &[or]);
+                }
+            }
+            Xor | Xnor => {
+                // With NOR gates every XOR in the chain comes out inverted
+                let want_inverted = recipe == Xnor;
+                let mut inverted = false;
+                let mut acc = inputs[0];
+                for (i, input) in inputs.iter().cloned().enumerate().skip(1) {
+                    if i + 1 == inputs.len() && inverted ^ self.nor == want_inverted {
+                        self.xor2(acc, input, output);
+                        return;
+                    }
+                    let xor = self.circuit.add_node(Self::gate_type(self.nor), self.nor);
+                    self.xor2(acc, input, xor);
+                    acc = xor;
+                    inverted ^= self.nor;
+                }
+                if inverted == want_inverted {
+                    self.buffer(acc, output);
+                } else {
+                    self.set_inputs(output, &[acc]);
+                }
+            }
+            Buffer | Not => unreachable!(),
+            Const(_) => unreachable!("Constant nodes have no inputs"),
+        }
+    }
+}
+
+impl Circuit {
+    /// Rewrites the circuit using only NAND gates and constants. Every
+    /// [NodeId] of this circuit refers to the equivalent node in the
+    /// rewritten one. Paths get longer, so timing isn't preserved, but
+    /// settled outputs are. Like [Self::extract], node outputs are copied,
+    /// so a settled circuit stays settled.
+    pub fn to_nand_only(&self) -> Circuit {
+        self.to_universal(false)
+    }
+
+    /// Like [Self::to_nand_only], but using only NOR gates.
+    pub fn to_nor_only(&self) -> Circuit {
+        self.to_universal(true)
+    }
+
+    fn to_universal(&self, nor: bool) -> Circuit {
+        let parents = self.node_parents();
+        let mut builder = UniversalBuilder {
+            circuit: Circuit::default(),
+            nor,
+        };
+        // Deleted ids are filled in too, so every id lines up, and deleted
+        // again once the rewrite no longer needs fresh ids
+        for node_id in (0..self.node_data.len() as u32).map(NodeId) {
+            let NodeData {
+                gate_type, output, ..
+            } = self.node_data[node_id];
+            let new_node = match gate_type {
+                GateType::Const => builder.circuit.add_node(GateType::Const, output),
+                _ => builder
+                    .circuit
+                    .add_node(UniversalBuilder::gate_type(nor), nor),
+            };
+            debug_assert_eq!(new_node, node_id);
+            builder.circuit.node_data[new_node].output = output;
+        }
+        for node_id in self.node_ids() {
+            if !parents[node_id].is_empty() {
+                builder.rewrite(self.node_kind(node_id), &parents[node_id], node_id);
+            }
+        }
+
+        let mut circuit = builder.circuit;
+        for node_id in self.deleted_node_ids() {
+            circuit.delete_node(node_id);
+        }
+        let parents = circuit.node_parents();
+        let node_ids: Vec<_> = circuit.node_ids().collect();
+        for node_id in node_ids {
+            let parent_outputs = parents[node_id]
+                .iter()
+                .map(|parent| circuit.node_data[*parent].output);
+            circuit.node_data[node_id].inputs =
+                Self::count_inputs(circuit.node_data[node_id].gate_type, parent_outputs);
+        }
+        circuit
+    }
+
+    /// Copies the cone of logic driving `outputs`, stopping at `inputs`, into
+    /// a new circuit where the `inputs` become primary inputs. Constants and
+    /// clocks in the cone are copied as they are, clocks keeping their phase.
+    /// Node outputs are copied over as well, so the extracted circuit starts
+    /// in the same state as the original if it was settled.
+    pub fn extract(
+        &self,
+        inputs: &[NodeId],
+        outputs: &[NodeId],
+    ) -> Result<Extracted, ExtractError> {
+        let parents = self.node_parents();
+
+        let mut circuit = Circuit::default();
+        let mut mapping = HashMap::new();
+        let mut extracted_inputs = Vec::with_capacity(inputs.len());
+        for input in inputs.iter().cloned() {
+            let new_input = *mapping.entry(input).or_insert_with(|| {
+                let node_data = &self.node_data[input];
+                let new_input = circuit.add_node(node_data.gate_type, node_data.inverted);
+                circuit.node_data[new_input].output = node_data.output;
+                new_input
+            });
+            extracted_inputs.push(new_input);
+        }
+
+        let mut cone = Vec::new();
+        let mut stack = outputs.to_vec();
+        while let Some(node_id) = stack.pop() {
+            if mapping.contains_key(&node_id) {
+                continue;
+            }
+            let clock = self.clocks.iter().find(|(clock, _, _)| *clock == node_id);
+            if parents[node_id].is_empty() && !self.is_const(node_id) && clock.is_none() {
+                return Err(ExtractError::UnboundedCone(node_id));
+            }
+            let NodeData {
+                gate_type,
+                inverted,
+                output,
+                ..
+            } = self.node_data[node_id];
+            let new_node = circuit.add_node(gate_type, inverted);
+            circuit.node_data[new_node].output = output;
+            if let Some((_, period, next)) = clock {
+                circuit
+                    .clocks
+                    .push((new_node, *period, next.saturating_sub(self.tick)));
+            }
+            mapping.insert(node_id, new_node);
+            cone.push(node_id);
+            stack.extend(parents[node_id].iter().cloned());
+        }
+
+        for node_id in cone.iter().cloned() {
+            let new_node = mapping[&node_id];
+            for parent in parents[node_id].iter() {
+                circuit.node_children[mapping[parent]].push(new_node);
+            }
+            let parent_outputs = parents[node_id]
+                .iter()
+                .map(|parent| self.node_data[*parent].output);
+            circuit.node_data[new_node].inputs =
+                Self::count_inputs(self.node_data[node_id].gate_type, parent_outputs);
+        }
+
+        let outputs = outputs.iter().map(|output| mapping[output]).collect();
+        Ok(Extracted {
+            circuit,
+            inputs: extracted_inputs,
+            outputs,
+        })
+    }
+
+    /// Limits every node to at most `max_fanout` children by inserting trees
+    /// of buffers in front of nodes driving more. Each level of buffers adds
+    /// a tick of delay to the paths going through it.
+    ///
+    /// Must be called on a settled circuit, since the new buffers start out
+    /// holding the output of the node they buffer.
+    pub fn insert_fanout_buffers(&mut self, max_fanout: usize) {
+        assert!(max_fanout >= 2, "Fanout of {max_fanout} can't be buffered");
+        assert!(
+            !self.work_left(),
+            "Circuit must be settled before buffering"
+        );
+
+        for node_id in self.node_ids().collect::<Vec<_>>() {
+            let output = self.node_data[node_id].output;
+            let mut level = std::mem::take(&mut self.node_children[node_id]);
+            while level.len() > max_fanout {
+                level = level
+                    .chunks(max_fanout)
+                    .map(|children| {
+                        let buffer = self.add_node(GateType::OrNor, false);
+                        let node_data = &mut self.node_data[buffer];
+                        node_data.output = output;
+                        node_data.inputs =
+                            Self::count_inputs(GateType::OrNor, [output].into_iter());
+                        self.node_children[buffer] = children.to_vec();
+                        buffer
+                    })
+                    .collect();
+            }
+            self.node_children[node_id] = level;
+        }
+    }
+
+    /// Collapses chains of single-input inverters by connecting each inverter
+    /// straight to the start of its chain. Inverters an even number of steps
+    /// down the chain become buffers. Every remaining node keeps its settled
+    /// output, but changes reach the end of a chain in a single tick.
+    /// Inverters in the middle of a chain that are left driving nothing are
+    /// deleted, so an odd chain ends up as a single inverter and an even one
+    /// as a buffer. Ones whose outputs are still read elsewhere are kept.
+    ///
+    /// Must be called on a settled circuit, since rewired nodes keep their
+    /// current output.
+    pub fn remove_double_inverters(&mut self) {
+        assert!(
+            !self.work_left(),
+            "Circuit must be settled before removing inverters"
+        );
+
+        let parents = self.node_parents();
+        let is_inverter = |node_id: NodeId| {
+            parents[node_id].len() == 1
+                && self.node_data[node_id].gate_type != GateType::Const
+                && self.inverts(node_id) == Some(true)
+        };
+        let mut rewires = Vec::new();
+        for node_id in self.node_ids() {
+            if !is_inverter(node_id) || !is_inverter(parents[node_id][0]) {
+                continue;
+            }
+            let mut source = parents[node_id][0];
+            let mut inverted = true;
+            let mut steps = 0;
+            // Inverter rings have no start, so give up after going around
+            while is_inverter(source) && steps <= self.node_data.len() {
+                source = parents[source][0];
+                inverted = !inverted;
+                steps += 1;
+            }
+            if !is_inverter(source) {
+                rewires.push((node_id, source, inverted));
+            }
+        }
+
+        let mut old_parents = Vec::with_capacity(rewires.len());
+        for (node_id, source, inverted) in rewires {
+            let old_parent = parents[node_id][0];
+            old_parents.push(old_parent);
+            let children = &mut self.node_children[old_parent];
+            let position = children.iter().position(|child| *child == node_id).unwrap();
+            children.remove(position);
+            self.node_children[source].push(node_id);
+
+            let source_output = self.node_data[source].output;
+            let node_data = &mut self.node_data[node_id];
+            debug_assert_eq!(node_data.output, source_output ^ inverted);
+            node_data.gate_type = GateType::OrNor;
+            node_data.inverted = inverted;
+            node_data.inputs = Self::count_inputs(GateType::OrNor, [source_output].into_iter());
+        }
+
+        // Nodes that never had children may be read as outputs, so only
+        // ones emptied by the rewiring are dead
+        old_parents.sort_unstable();
+        old_parents.dedup();
+        for old_parent in old_parents {
+            if self.node_children[old_parent].is_empty() {
+                self.delete_node(old_parent);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, sync::Arc};
+
+    use crate::{
+        circuit_builder::{ops::*, CircuitBuilder, Connector},
+        circuit_sim::CircuitSim,
+        components::adder::RippleCarryAdder,
+    };
+
+    use rand::Rng;
+
+    use super::{ExtractError, Extracted};
+    use crate::{circuit_sim::NodeType, test_utils::seeded_rng, Circuit};
+    use std::collections::HashMap;
+
+    #[test]
+    fn extract_adder_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let (cin, cin_id) = Connector::input(builder.clone());
+        let rca = RippleCarryAdder::<4>::new(builder.clone(), cin);
+        // Surrounding logic that shouldn't end up in the extracted adder
+        let (other, _) = Connector::input(builder.clone());
+        let (extra, _) = Connector::input(builder.clone());
+        let _ = and!(other, extra);
+
+        let mut borrow = builder.borrow_mut();
+        let (circuit, _) = borrow.build();
+        circuit.run_until_done();
+
+        let mut inputs = vec![cin_id];
+        inputs.extend(rca.input_a);
+        inputs.extend(rca.input_b);
+        let mut outputs = vec![rca.cout];
+        outputs.extend(rca.sum);
+
+        let mut extracted = circuit.extract(&inputs, &outputs).unwrap();
+        assert!(extracted.circuit.num_nodes() < circuit.num_nodes());
+        for val in 0..(1 << inputs.len()) {
+            let values: Vec<_> = (0..inputs.len()).map(|i| val & (1 << i) != 0).collect();
+            let original: Vec<_> = inputs.iter().cloned().zip(values.iter().cloned()).collect();
+            let copy: Vec<_> = extracted
+                .inputs
+                .iter()
+                .cloned()
+                .zip(values.iter().cloned())
+                .collect();
+            assert_eq!(
+                circuit.apply(&original, &outputs),
+                extracted.circuit.apply(&copy, &extracted.outputs),
+                "inputs {val:#b}"
+            );
+        }
+
+        assert_eq!(
+            circuit.extract(&inputs[1..], &outputs).unwrap_err(),
+            ExtractError::UnboundedCone(cin_id)
+        );
+    }
+
+    #[test]
+    fn extract_sources_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let one = circuit.constant(true);
+        let clock = circuit.clock(3);
+        let and = circuit.create_node(NodeType::And);
+        let xor = circuit.create_node(NodeType::Xor);
+        for (from, to) in [(input, and), (one, and), (and, xor), (clock, xor)] {
+            circuit.connect(from, to);
+        }
+        circuit.set_input(input, true);
+        circuit.run(4);
+
+        let Extracted {
+            circuit: mut extracted,
+            inputs,
+            outputs,
+        } = circuit.extract(&[input], &[xor]).unwrap();
+        assert_eq!(extracted.num_nodes(), 5);
+        for _ in 0..10 {
+            assert_eq!(
+                extracted.get_output(outputs[0]),
+                circuit.get_output(xor),
+                "tick {}",
+                circuit.tick()
+            );
+            circuit.update();
+            extracted.update();
+        }
+        assert_eq!(
+            extracted.apply(&[(inputs[0], false)], &outputs),
+            circuit.apply(&[(input, false)], &[xor])
+        );
+    }
+
+    #[test]
+    fn fanout_buffers_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let outputs: Vec<_> = (0..100)
+            .map(|_| {
+                let output = circuit.create_node(NodeType::Nor);
+                circuit.connect(input, output);
+                output
+            })
+            .collect();
+        circuit.run_until_done();
+
+        circuit.insert_fanout_buffers(4);
+        let mut fanout = HashMap::new();
+        for (input, _) in circuit.edges() {
+            *fanout.entry(input).or_insert(0) += 1;
+        }
+        assert!(fanout.values().all(|fanout| *fanout <= 4), "{fanout:?}");
+        assert!(outputs.iter().all(|output| circuit.get_output(*output)));
+
+        for val in [true, false, true] {
+            let results = circuit.apply(&[(input, val)], &outputs);
+            assert!(results.iter().all(|result| *result != val));
+        }
+    }
+
+    #[test]
+    fn remove_double_inverters_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let chain = circuit.create_nodes(NodeType::Nor, 5);
+        circuit.connect(input, chain[0]);
+        for pair in chain.windows(2) {
+            circuit.connect(pair[0], pair[1]);
+        }
+        circuit.run_until_done();
+        let output = chain[4];
+        let before: Vec<_> = [true, false]
+            .into_iter()
+            .map(|val| circuit.apply(&[(input, val)], &[output]))
+            .collect();
+
+        circuit.remove_double_inverters();
+        assert_eq!(circuit.num_nodes(), 2);
+        assert_eq!(circuit.edges().collect::<Vec<_>>(), [(input, output)]);
+        assert_eq!(circuit.node_kind(output), NodeType::Nor);
+        let after: Vec<_> = [true, false]
+            .into_iter()
+            .map(|val| circuit.apply(&[(input, val)], &[output]))
+            .collect();
+        assert_eq!(before, after);
+
+        // An even chain leaves a buffer, and taps in the middle are kept
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let chain = circuit.create_nodes(NodeType::Nor, 4);
+        circuit.connect(input, chain[0]);
+        for pair in chain.windows(2) {
+            circuit.connect(pair[0], pair[1]);
+        }
+        let tap = circuit.create_node(NodeType::And);
+        circuit.connect(chain[1], tap);
+        circuit.connect(input, tap);
+        circuit.run_until_done();
+        let outputs = [chain[1], chain[3], tap];
+        let before: Vec<_> = [true, false]
+            .into_iter()
+            .map(|val| circuit.apply(&[(input, val)], &outputs))
+            .collect();
+
+        circuit.remove_double_inverters();
+        assert_eq!(circuit.num_nodes(), 4);
+        assert_eq!(
+            circuit.node_ids().collect::<Vec<_>>(),
+            [input, chain[1], chain[3], tap]
+        );
+        assert_eq!(circuit.node_kind(chain[1]), NodeType::Or);
+        assert_eq!(circuit.node_kind(chain[3]), NodeType::Or);
+        assert_eq!(circuit.levels()[chain[3]], 1);
+        let after: Vec<_> = [true, false]
+            .into_iter()
+            .map(|val| circuit.apply(&[(input, val)], &outputs))
+            .collect();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn universal_gates_test() {
+        use NodeType::*;
+        let mut circuit = Circuit::new();
+        let inputs = [(); 3].map(|_| circuit.create_input());
+        let mut outputs = Vec::new();
+        for node_type in [Or, Nor, And, Nand, Xor, Xnor] {
+            for fan_in in 1..=3 {
+                let output = circuit.create_node(node_type);
+                for input in inputs[..fan_in].iter().cloned() {
+                    circuit.connect(input, output);
+                }
+                outputs.push(output);
+            }
+        }
+        let one = circuit.create_node(Const(true));
+        let and = circuit.create_node(And);
+        circuit.connect(one, and);
+        circuit.connect(inputs[0], and);
+        outputs.push(and);
+        circuit.run_until_done();
+
+        for (mut rewritten, universal) in
+            [(circuit.to_nand_only(), Nand), (circuit.to_nor_only(), Nor)]
+        {
+            assert!(!rewritten.work_left());
+            assert!(rewritten
+                .node_ids()
+                .all(|node_id| [universal, Const(true)].contains(&rewritten.node_kind(node_id))));
+            for val in 0..8 {
+                let inputs: Vec<_> = (0..3).map(|i| (inputs[i], val & (1 << i) != 0)).collect();
+                assert_eq!(
+                    circuit.apply(&inputs, &outputs),
+                    rewritten.apply(&inputs, &outputs),
+                    "{universal:?} with inputs {val:03b}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn nand_only_adder_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let (cin, cin_id) = Connector::input(builder.clone());
+        let rca = RippleCarryAdder::<8>::new(builder.clone(), cin);
+        let mut borrow = builder.borrow_mut();
+        let (circuit, _) = borrow.build();
+        circuit.run_until_done();
+
+        let mut nand_only = circuit.to_nand_only();
+        assert!(nand_only
+            .node_ids()
+            .all(|node_id| nand_only.node_kind(node_id) == NodeType::Nand));
+        let mut rng = seeded_rng();
+        for _ in 0..100 {
+            let (a, b, c) = (rng.gen::<u8>(), rng.gen::<u8>(), rng.gen::<bool>());
+            let mut inputs = vec![(cin_id, c)];
+            for i in 0..8 {
+                inputs.push((rca.input_a[i], a & (1 << i) != 0));
+                inputs.push((rca.input_b[i], b & (1 << i) != 0));
+            }
+            let sum = nand_only
+                .apply(&inputs, &rca.sum)
+                .into_iter()
+                .enumerate()
+                .fold(0, |sum, (i, bit)| sum | ((bit as u32) << i));
+            let cout = nand_only.get_output(rca.cout) as u32;
+            assert_eq!(sum | cout << 8, a as u32 + b as u32 + c as u32);
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::{cell::RefCell, sync::Arc};
 
 use crate::circuit_sim::*;
-use crate::{Circuit, InputId, NodeId};
+use crate::{Circuit, InputId, LoopKind, NodeId};
 
 pub trait BuilderHooks: Default {
     fn create_node_hook(&mut self, _node_id: NodeId) {}
@@ -18,6 +18,18 @@ impl BuilderHooks for NoHooks {
     type MarkNodeArgs = ();
 }
 
+/// Hooks that keep each mark as the node's name, for
+/// [CircuitBuilderWithHooks::build_with_names] to hand to the circuit.
+#[derive(Default)]
+pub struct Names(Vec<(NodeId, String)>);
+impl BuilderHooks for Names {
+    type MarkNodeArgs = String;
+
+    fn mark_node(&mut self, node_id: NodeId, name: String) {
+        self.0.push((node_id, name));
+    }
+}
+
 pub type CircuitBuilder = CircuitBuilderWithHooks<NoHooks>;
 
 #[derive(Default)]
@@ -49,9 +61,45 @@ impl<T: BuilderHooks> CircuitBuilderWithHooks<T> {
         self.hooks.mark_node(node_id, args);
     }
 
+    /// Groups the nodes created until the matching [Self::end_module] under
+    /// `name`, see [Circuit::begin_module].
+    pub fn begin_module(&mut self, name: &str) {
+        self.circuit.begin_module(name);
+    }
+
+    pub fn end_module(&mut self) {
+        self.circuit.end_module();
+    }
+
     pub fn build(&mut self) -> (&mut Circuit, &mut T) {
         (&mut self.circuit, &mut self.hooks)
     }
+
+    /// Feedback loops that can't hold a stable value, like inverter rings,
+    /// which would keep [CircuitSim::run_until_done] from ever returning.
+    /// Latches are left out. Meant to be checked after building and before
+    /// running, see [Circuit::classify_loops] for the details.
+    pub fn find_combinational_loops(&self) -> Vec<Vec<NodeId>> {
+        self.circuit
+            .classify_loops()
+            .into_iter()
+            .filter_map(|kind| match kind {
+                LoopKind::Latch(_) => None,
+                LoopKind::Oscillator(nodes) => Some(nodes),
+            })
+            .collect()
+    }
+}
+
+impl CircuitBuilderWithHooks<Names> {
+    /// Like [Self::build], but first names every marked node after its mark
+    /// with [Circuit::set_node_name], so exports use the marks as labels.
+    pub fn build_with_names(&mut self) -> &mut Circuit {
+        for (node_id, name) in self.hooks.0.drain(..) {
+            self.circuit.set_node_name(node_id, name);
+        }
+        &mut self.circuit
+    }
 }
 
 pub struct Connector<T: BuilderHooks> {
@@ -75,17 +123,31 @@ impl<T: BuilderHooks> Connector<T> {
         (Self::from_output(builder.clone(), input_id), input_id)
     }
 
+    pub fn constant(builder: Arc<RefCell<CircuitBuilderWithHooks<T>>>, val: bool) -> Self {
+        let output = builder.borrow_mut().create_node(NodeType::Const(val));
+        Self::from_output(builder.clone(), output)
+    }
+
     pub fn input_ignore(builder: Arc<RefCell<CircuitBuilderWithHooks<T>>>) -> Self {
         let (connector, _input_id) = Self::input(builder);
         connector
     }
 
-    fn gate_gen<'a>(node_type: NodeType, inputs: &[&'a Self]) -> Self {
+    fn assert_same_builder(&self, other: &Self) {
+        assert!(
+            Arc::ptr_eq(&self.builder, &other.builder),
+            "Connectors {:?} and {:?} belong to different builders",
+            self.output,
+            other.output
+        );
+    }
+
+    fn gate_gen(node_type: NodeType, inputs: &[&Self]) -> Self {
         let builder = inputs[0].builder.clone();
         let mut builder_mut = builder.borrow_mut();
         let output = builder_mut.create_node(node_type);
         for input in inputs {
-            assert!(Arc::ptr_eq(&builder, &input.builder));
+            Self::assert_same_builder(inputs[0], input);
             let input = input.output;
             builder_mut.connect(input, output);
         }
@@ -99,12 +161,13 @@ impl<T: BuilderHooks> Connector<T> {
 
     pub fn invert(&self) -> Self {
         let mut builder_mut = self.builder.borrow_mut();
-        let inverter = builder_mut.create_node(NodeType::Nor);
+        let inverter = builder_mut.create_node(NodeType::Not);
         builder_mut.connect(self.output, inverter);
         Self::from_output(self.builder.clone(), inverter)
     }
 
     pub fn connect(&self, output: &Connector<T>) {
+        self.assert_same_builder(output);
         self.builder
             .borrow_mut()
             .connect(self.output, output.output);
@@ -0,0 +1,251 @@
+use std::{collections::HashMap, hash::Hash, ops::Index};
+
+use crate::circuit_sim::*;
+use crate::{Circuit, NodeId};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FragmentNodeId(u32);
+
+#[derive(Clone, Copy, Debug)]
+enum FragmentNode {
+    Gate(NodeType),
+    Port(usize),
+}
+
+/// A piece of circuit built independently of any [Circuit], using ids local
+/// to the fragment. Fragments own all their data, so they can be built on
+/// separate threads and later [Circuit::merge]d into a circuit.
+///
+/// Ports are placeholders for nodes that live outside of the fragment (e.g.
+/// shared address or data lines) and are bound when merging.
+#[derive(Clone, Debug, Default)]
+pub struct CircuitFragment {
+    nodes: Vec<FragmentNode>,
+    edges: Vec<(FragmentNodeId, FragmentNodeId)>,
+    inits: Vec<(FragmentNodeId, bool)>,
+    num_ports: usize,
+}
+
+impl CircuitFragment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add(&mut self, node: FragmentNode) -> FragmentNodeId {
+        let node_id = FragmentNodeId(self.nodes.len() as u32);
+        self.nodes.push(node);
+        node_id
+    }
+
+    pub fn create_node(&mut self, node_type: NodeType) -> FragmentNodeId {
+        self.add(FragmentNode::Gate(node_type))
+    }
+
+    pub fn create_input(&mut self) -> FragmentNodeId {
+        self.create_node(NodeType::Or)
+    }
+
+    /// Creates a placeholder bound to the `n`th entry of the `ports` passed
+    /// to [Circuit::merge], where `n` is the number of ports created before.
+    pub fn create_port(&mut self) -> FragmentNodeId {
+        let port = self.num_ports;
+        self.num_ports += 1;
+        self.add(FragmentNode::Port(port))
+    }
+
+    pub fn connect(&mut self, input: FragmentNodeId, output: FragmentNodeId) {
+        self.edges.push((input, output));
+    }
+
+    /// Sets the output of a node once merged, like [CircuitSim::set_input].
+    pub fn set_input(&mut self, node_id: FragmentNodeId, val: bool) {
+        self.inits.push((node_id, val));
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn num_ports(&self) -> usize {
+        self.num_ports
+    }
+}
+
+/// Mapping from the ids of a merged [CircuitFragment] to their [NodeId]s.
+#[derive(Clone, Debug)]
+pub struct FragmentNodes(Vec<NodeId>);
+
+impl Index<FragmentNodeId> for FragmentNodes {
+    type Output = NodeId;
+
+    fn index(&self, index: FragmentNodeId) -> &Self::Output {
+        &self.0[index.0 as usize]
+    }
+}
+
+impl Circuit {
+    pub fn merge(&mut self, fragment: &CircuitFragment, ports: &[NodeId]) -> FragmentNodes {
+        assert_eq!(
+            ports.len(),
+            fragment.num_ports,
+            "Fragment has {} ports but {} were given",
+            fragment.num_ports,
+            ports.len()
+        );
+        let node_ids = fragment
+            .nodes
+            .iter()
+            .map(|node| match *node {
+                FragmentNode::Gate(node_type) => self.create_node(node_type),
+                FragmentNode::Port(port) => ports[port],
+            })
+            .collect();
+        let node_ids = FragmentNodes(node_ids);
+        for (input, output) in fragment.edges.iter().cloned() {
+            self.connect(node_ids[input], node_ids[output]);
+        }
+        for (node_id, val) in fragment.inits.iter().cloned() {
+            self.set_input(node_ids[node_id], val);
+        }
+        node_ids
+    }
+}
+
+/// Builds each kind of component once as a [CircuitFragment] and merges
+/// copies of it afterwards. Keys identify both the kind of component and
+/// the parameters it was built with, since the cache can't tell builder
+/// functions apart. `T` is whatever the builder returns alongside the
+/// fragment, e.g. the [FragmentNodeId]s of its outputs.
+pub struct ComponentCache<K, T> {
+    components: HashMap<K, (CircuitFragment, T)>,
+    builds: usize,
+}
+
+impl<K: Eq + Hash, T: Clone> ComponentCache<K, T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merges the component for `key` into `circuit` like [Circuit::merge],
+    /// only calling `build` if it hasn't been built before.
+    pub fn merge<F>(
+        &mut self,
+        circuit: &mut Circuit,
+        key: K,
+        ports: &[NodeId],
+        build: F,
+    ) -> (FragmentNodes, T)
+    where
+        F: FnOnce() -> (CircuitFragment, T),
+    {
+        let builds = &mut self.builds;
+        let (fragment, extra) = self.components.entry(key).or_insert_with(|| {
+            *builds += 1;
+            build()
+        });
+        (circuit.merge(fragment, ports), extra.clone())
+    }
+
+    /// How many times a builder was actually called.
+    pub fn builds(&self) -> usize {
+        self.builds
+    }
+}
+
+impl<K, T> Default for ComponentCache<K, T> {
+    fn default() -> Self {
+        Self {
+            components: HashMap::new(),
+            builds: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use crate::{circuit_sim::*, Circuit};
+
+    use super::{CircuitFragment, ComponentCache, FragmentNodeId};
+
+    fn half_adder() -> (CircuitFragment, FragmentNodeId, FragmentNodeId) {
+        let mut fragment = CircuitFragment::new();
+        let a = fragment.create_port();
+        let b = fragment.create_port();
+        let sum = fragment.create_node(NodeType::Xor);
+        let carry = fragment.create_node(NodeType::And);
+        for input in [a, b] {
+            fragment.connect(input, sum);
+            fragment.connect(input, carry);
+        }
+        (fragment, sum, carry)
+    }
+
+    #[test]
+    fn threaded_merge_test() {
+        let handles: Vec<_> = (0..2).map(|_| thread::spawn(half_adder)).collect();
+        let fragments: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        let mut circuit = Circuit::new();
+        let a = circuit.create_input();
+        let b = circuit.create_input();
+        let c = circuit.create_input();
+        let (fragment, sum, carry) = &fragments[0];
+        let first = circuit.merge(fragment, &[a, b]);
+        let second = circuit.merge(&fragments[1].0, &[b, c]);
+        let (sum, carry) = (*sum, *carry);
+
+        for i in 0..8 {
+            let (va, vb, vc) = (i & 1 != 0, i & 2 != 0, i & 4 != 0);
+            circuit.set_input(a, va);
+            circuit.set_input(b, vb);
+            circuit.set_input(c, vc);
+            circuit.run_until_done();
+            assert_eq!(circuit.get_output(first[sum]), va ^ vb);
+            assert_eq!(circuit.get_output(first[carry]), va && vb);
+            assert_eq!(circuit.get_output(second[sum]), vb ^ vc);
+            assert_eq!(circuit.get_output(second[carry]), vb && vc);
+        }
+    }
+
+    #[test]
+    fn component_cache_test() {
+        const CELLS: usize = 32;
+        let mut cached = Circuit::new();
+        let mut individual = Circuit::new();
+        let mut cache = ComponentCache::new();
+        let inputs: Vec<_> = (0..=CELLS).map(|_| cached.create_input()).collect();
+        for _ in 0..=CELLS {
+            individual.create_input();
+        }
+
+        let mut outputs = Vec::new();
+        for i in 0..CELLS {
+            let ports = [inputs[i], inputs[i + 1]];
+            let (nodes, (sum, carry)) = cache.merge(&mut cached, "half_adder", &ports, || {
+                let (fragment, sum, carry) = half_adder();
+                (fragment, (sum, carry))
+            });
+            outputs.extend([nodes[sum], nodes[carry]]);
+
+            let (fragment, sum, carry) = half_adder();
+            let nodes = individual.merge(&fragment, &ports);
+            assert_eq!([nodes[sum], nodes[carry]], outputs[outputs.len() - 2..]);
+        }
+        assert_eq!(cache.builds(), 1);
+        assert_eq!(cached.num_nodes(), individual.num_nodes());
+
+        for val in [0u64, 0x1_5555_5555, 0x1_ffff_ffff, 0x0_1234_5678] {
+            let inputs: Vec<_> = inputs
+                .iter()
+                .enumerate()
+                .map(|(i, input)| (*input, val & (1 << i) != 0))
+                .collect();
+            assert_eq!(
+                cached.apply(&inputs, &outputs),
+                individual.apply(&inputs, &outputs)
+            );
+        }
+    }
+}
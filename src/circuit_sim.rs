@@ -4,7 +4,7 @@ use std::hash::Hash;
 pub type Tick = u64;
 pub type Ticks = u64;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum NodeType {
     Or,
     Nor,
@@ -12,12 +12,30 @@ pub enum NodeType {
     Nand,
     Xor,
     Xnor,
+    /// Outputs its single input. It's an alias for an [NodeType::Or] that
+    /// should only get one input, and nothing checks that it does, so
+    /// connecting more makes it an OR of them.
+    Buffer,
+    /// Outputs the inverse of its single input. Like [NodeType::Buffer],
+    /// it's an alias for a [NodeType::Nor] that should only get one input.
+    Not,
+    /// Always outputs the given value. Can't be connected into or set.
+    Const(bool),
 }
 
 #[derive(Debug)]
-pub enum RunResult {
+pub enum RunResult<NodeId> {
+    /// There was no work to do when the run started.
+    AlreadyStable,
+    /// The circuit became stable after running for some ticks.
     Finished { after_ticks: Ticks },
-    ReachedMaxTicks { max_ticks: Ticks },
+    /// The circuit was still changing when it ran out of ticks, likely
+    /// because of an oscillating loop.
+    ReachedMaxTicks {
+        max_ticks: Ticks,
+        /// The nodes that were still waiting to be updated.
+        pending: Vec<NodeId>,
+    },
 }
 
 pub trait CircuitSim {
@@ -27,19 +45,47 @@ pub trait CircuitSim {
     fn new() -> Self;
 
     fn tick(&self) -> Tick;
+    /// How many nodes are in the circuit, not counting deleted ones.
     fn num_nodes(&self) -> usize;
     fn get_output(&self, node_id: Self::NodeId) -> bool;
     fn work_left(&self) -> bool;
 
+    /// Whether the circuit has settled, i.e. updating it would change nothing.
+    fn is_stable(&self) -> bool {
+        !self.work_left()
+    }
+
     fn update(&mut self);
     fn connect(&mut self, input: Self::NodeId, output: Self::NodeId);
+    /// Removes one connection from `input` into `output`, undoing a single
+    /// [Self::connect]. Does nothing if they aren't connected.
+    fn disconnect(&mut self, input: Self::NodeId, output: Self::NodeId);
+    /// Disconnects the node from everything and frees its id to be reused
+    /// by a later [Self::create_node]. The id must not be used after this.
+    fn delete_node(&mut self, node_id: Self::NodeId);
 
     fn create_node(&mut self, node_type: NodeType) -> Self::NodeId;
     fn create_input(&mut self) -> Self::InputId;
+    /// A node that starts low and toggles every `period_ticks` updates, so
+    /// a full cycle takes twice that. Clocks don't count as work left, so
+    /// [Self::run] stops once everything else settles. Nothing should be
+    /// connected into a clock.
+    fn clock(&mut self, period_ticks: Ticks) -> Self::NodeId;
+    /// A node tied to `val`, for tie-high and tie-low rails.
+    fn constant(&mut self, val: bool) -> Self::NodeId {
+        self.create_node(NodeType::Const(val))
+    }
 
     fn set_input(&mut self, node_id: Self::InputId, val: bool);
 
-    fn run(&mut self, max_ticks: Ticks) -> RunResult {
+    /// Nodes waiting to be updated, for finding out what keeps the circuit
+    /// from settling.
+    fn pending_nodes(&self) -> Vec<Self::NodeId>;
+
+    fn run(&mut self, max_ticks: Ticks) -> RunResult<Self::NodeId> {
+        if self.is_stable() {
+            return RunResult::AlreadyStable;
+        }
         for ticks in 0..max_ticks {
             if self.work_left() {
                 self.update();
@@ -47,7 +93,10 @@ pub trait CircuitSim {
                 return RunResult::Finished { after_ticks: ticks };
             };
         }
-        RunResult::ReachedMaxTicks { max_ticks }
+        RunResult::ReachedMaxTicks {
+            max_ticks,
+            pending: self.pending_nodes(),
+        }
     }
 
     fn run_until_done(&mut self) {
@@ -55,4 +104,28 @@ pub trait CircuitSim {
             self.update();
         }
     }
+
+    /// Sets a single input and runs until stable, returning how many updates
+    /// it took for the change to settle.
+    fn settle_cost(&mut self, input: Self::InputId, val: bool) -> usize {
+        self.set_input(input, val);
+        let mut updates = 0;
+        while self.work_left() {
+            self.update();
+            updates += 1;
+        }
+        updates
+    }
+
+    /// Sets all the `inputs`, runs until stable and reads back the `outputs`.
+    fn apply(&mut self, inputs: &[(Self::InputId, bool)], outputs: &[Self::NodeId]) -> Vec<bool> {
+        for (input, val) in inputs.iter().cloned() {
+            self.set_input(input, val);
+        }
+        self.run_until_done();
+        outputs
+            .iter()
+            .map(|output| self.get_output(*output))
+            .collect()
+    }
 }
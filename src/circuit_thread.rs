@@ -0,0 +1,149 @@
+use std::{
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    circuit_sim::{CircuitSim, Ticks},
+    Circuit, InputId, NodeId,
+};
+
+/// How many ticks the thread runs between checking for new inputs.
+const TICKS_PER_BATCH: Ticks = 1024;
+
+/// A [Circuit] simulated on its own thread, so callers never wait for it to
+/// settle. Inputs are sent to the thread and changes to the watched nodes
+/// are sent back, both over channels. The thread sleeps while the circuit
+/// is stable, and stops when this is dropped or joined.
+pub struct CircuitThread {
+    inputs: Option<Sender<(InputId, bool)>>,
+    changes: Receiver<(NodeId, bool)>,
+    handle: Option<JoinHandle<Circuit>>,
+}
+
+impl CircuitThread {
+    /// Moves `circuit` onto a new thread, reporting changes to the outputs
+    /// of the `watched` nodes.
+    pub fn spawn(circuit: Circuit, watched: Vec<NodeId>) -> Self {
+        let (input_sender, input_receiver) = mpsc::channel();
+        let (change_sender, change_receiver) = mpsc::channel();
+        let handle =
+            thread::spawn(move || simulate(circuit, watched, input_receiver, change_sender));
+        CircuitThread {
+            inputs: Some(input_sender),
+            changes: change_receiver,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn send_input(&self, input: InputId, val: bool) {
+        self.inputs
+            .as_ref()
+            .unwrap()
+            .send((input, val))
+            .expect("Circuit thread panicked");
+    }
+
+    /// The watched nodes that changed since the last call, with their new
+    /// output, oldest first. The thread only samples them between batches
+    /// of 1024 ticks, so a node that toggles and toggles back within a batch
+    /// isn't reported. Doesn't block, so changes still being
+    /// simulated come in later calls.
+    pub fn recv_changes(&self) -> Vec<(NodeId, bool)> {
+        self.changes.try_iter().collect()
+    }
+
+    /// Stops the thread once the inputs sent so far have been applied, and
+    /// returns the circuit, which may not have settled.
+    pub fn join(mut self) -> Circuit {
+        self.stop().unwrap()
+    }
+
+    fn stop(&mut self) -> Option<Circuit> {
+        // Hanging up is what tells the thread to stop
+        self.inputs.take();
+        let handle = self.handle.take()?;
+        Some(handle.join().expect("Circuit thread panicked"))
+    }
+}
+
+impl Drop for CircuitThread {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            self.stop();
+        }
+    }
+}
+
+fn simulate(
+    mut circuit: Circuit,
+    watched: Vec<NodeId>,
+    inputs: Receiver<(InputId, bool)>,
+    changes: Sender<(NodeId, bool)>,
+) -> Circuit {
+    let mut outputs: Vec<_> = watched
+        .iter()
+        .map(|node_id| circuit.get_output(*node_id))
+        .collect();
+    loop {
+        if circuit.is_stable() {
+            match inputs.recv() {
+                Ok((input, val)) => circuit.set_input(input, val),
+                Err(_) => return circuit,
+            }
+        }
+        loop {
+            match inputs.try_recv() {
+                Ok((input, val)) => circuit.set_input(input, val),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return circuit,
+            }
+        }
+
+        circuit.run(TICKS_PER_BATCH);
+        for (node_id, output) in watched.iter().cloned().zip(outputs.iter_mut()) {
+            let new_output = circuit.get_output(node_id);
+            if *output != new_output {
+                *output = new_output;
+                // Nobody is listening if the receiver is gone
+                let _ = changes.send((node_id, new_output));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        circuit_sim::{CircuitSim, NodeType},
+        Circuit,
+    };
+
+    use super::CircuitThread;
+
+    #[test]
+    fn circuit_thread_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let output = circuit.create_node(NodeType::Nor);
+        circuit.connect(input, output);
+        circuit.run_until_done();
+
+        let thread = CircuitThread::spawn(circuit, vec![output]);
+        assert!(thread.recv_changes().is_empty());
+        thread.send_input(input, true);
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut changes = Vec::new();
+        while changes.is_empty() && Instant::now() < deadline {
+            changes = thread.recv_changes();
+            std::thread::yield_now();
+        }
+        assert_eq!(changes, vec![(output, false)]);
+
+        let circuit = thread.join();
+        assert!(!circuit.get_output(output));
+    }
+}
@@ -12,7 +12,7 @@ struct Adder {
     cout: Connector,
 }
 
-fn adder(a: Connector, b: Connector, cin: Connector) -> Adder {
+fn adder(a: &Connector, b: &Connector, cin: &Connector) -> Adder {
     let sum = xor!(a, b, cin);
     let cout = or!(and!(a, b), and!(a, cin), and!(b, cin));
     Adder { sum, cout }
@@ -29,6 +29,7 @@ pub struct RippleCarryAdder<const BITS: usize> {
 impl<const BITS: usize> RippleCarryAdder<BITS> {
     pub fn new(builder: Arc<RefCell<CircuitBuilder>>, cin: Connector) -> RippleCarryAdder<BITS> {
         assert!(BITS > 0);
+        builder.borrow_mut().begin_module("ripple_carry_adder");
 
         let mut rca = Self {
             input_a: [InputId::default(); BITS],
@@ -45,15 +46,73 @@ impl<const BITS: usize> RippleCarryAdder<BITS> {
             let b = Connector::input_ignore(builder.clone());
             rca.input_a[i] = a.output;
             rca.input_b[i] = b.output;
-            let Adder { sum, cout } = adder(a, b, carry);
+            let Adder { sum, cout } = adder(&a, &b, &carry);
             rca.sum[i] = sum.output;
             carry = cout;
         }
         rca.cout = carry.output;
+        builder.borrow_mut().end_module();
         rca
     }
 }
 
+/// Adder that clamps to the largest or smallest representable value on
+/// overflow instead of wrapping around. Unsigned sums saturate to all ones
+/// when there's a carry out. Signed two's complement sums overflow when
+/// both inputs have the same sign and the sum doesn't, and saturate towards
+/// the sign of the inputs.
+pub struct SaturatingAdder<const BITS: usize> {
+    pub input_a: [InputId; BITS],
+    pub input_b: [InputId; BITS],
+    /// Set when the sum was clamped.
+    pub overflow: NodeId,
+    pub sum: [NodeId; BITS],
+}
+
+impl<const BITS: usize> SaturatingAdder<BITS> {
+    pub fn new(builder: Arc<RefCell<CircuitBuilder>>, signed: bool) -> SaturatingAdder<BITS> {
+        assert!(BITS > 0);
+        builder.borrow_mut().begin_module("saturating_adder");
+        let a = [(); BITS].map(|_| Connector::input_ignore(builder.clone()));
+        let b = [(); BITS].map(|_| Connector::input_ignore(builder.clone()));
+        let mut carry = Connector::constant(builder.clone(), false);
+        let mut wrapped = Vec::with_capacity(BITS);
+        for i in 0..BITS {
+            let Adder { sum, cout } = adder(&a[i], &b[i], &carry);
+            wrapped.push(sum);
+            carry = cout;
+        }
+
+        let (sum, overflow) = if signed {
+            let msb = BITS - 1;
+            let overflow = and!(xnor!(a[msb], b[msb]), xor!(wrapped[msb], a[msb]));
+            let keep = overflow.invert();
+            // Positive overflow saturates to 0111..., negative to 1000...
+            let saturated_low = a[msb].invert();
+            let sum: Vec<_> = wrapped
+                .iter()
+                .enumerate()
+                .map(|(i, bit)| {
+                    let saturated = if i == msb { &a[msb] } else { &saturated_low };
+                    or!(and!(bit, keep), and!(saturated, overflow))
+                })
+                .collect();
+            (sum, overflow)
+        } else {
+            let sum: Vec<_> = wrapped.iter().map(|bit| or!(bit, carry)).collect();
+            (sum, carry)
+        };
+
+        builder.borrow_mut().end_module();
+        Self {
+            input_a: a.map(|connector| connector.output),
+            input_b: b.map(|connector| connector.output),
+            overflow: overflow.output,
+            sum: std::array::from_fn(|i| sum[i].output),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use rand::RngCore;
@@ -61,18 +120,20 @@ mod test {
     use crate::{
         circuit_builder::{CircuitBuilder, Connector},
         circuit_sim::CircuitSim,
+        test_utils::seeded_rng,
         Circuit,
     };
     use std::{cell::RefCell, sync::Arc};
 
-    use super::{adder, RippleCarryAdder};
+    use super::{adder, RippleCarryAdder, SaturatingAdder};
 
+    #[allow(clippy::nonminimal_bool)]
     fn test_adder(a: bool, b: bool, cin: bool) {
         let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
         let (ca, ia) = Connector::input(builder.clone());
         let (cb, ib) = Connector::input(builder.clone());
         let (ccin, icin) = Connector::input(builder.clone());
-        let adder = adder(ca, cb, ccin);
+        let adder = adder(&ca, &cb, &ccin);
         {
             let mut borrow = builder.borrow_mut();
             let (circuit, _) = borrow.build();
@@ -145,11 +206,72 @@ mod test {
         let rca = RippleCarryAdder::<16>::new(builder.clone(), Connector::new(builder.clone()));
         let mut borrow = builder.borrow_mut();
         let (circuit, _) = borrow.build();
-        let mut rng = rand::thread_rng();
+        let mut rng = seeded_rng();
         for _ in 0..100 {
             let a = rng.next_u32() as u16;
             let b = rng.next_u32() as u16;
             test_rca_add(circuit, &rca, a as u64, b as u64);
         }
     }
+
+    #[test]
+    fn saturating_adder_test() {
+        for signed in [false, true] {
+            let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+            let adder = SaturatingAdder::<4>::new(builder.clone(), signed);
+            let mut borrow = builder.borrow_mut();
+            let (circuit, _) = borrow.build();
+            circuit.run_until_done();
+
+            let value = |bits: u64| {
+                if signed {
+                    ((bits as i64) << 60) >> 60
+                } else {
+                    bits as i64
+                }
+            };
+            let (min, max) = if signed { (-8, 7) } else { (0, 15) };
+            for a in 0..16u64 {
+                for b in 0..16u64 {
+                    for i in 0..4 {
+                        circuit.set_input(adder.input_a[i], a & (1 << i) != 0);
+                        circuit.set_input(adder.input_b[i], b & (1 << i) != 0);
+                    }
+                    circuit.run_until_done();
+                    let sum = (0..4)
+                        .filter(|i| circuit.get_output(adder.sum[*i]))
+                        .fold(0, |sum, i| sum | 1 << i);
+
+                    let exact = value(a) + value(b);
+                    let expected = exact.clamp(min, max);
+                    let context = format!("{} + {} signed: {signed}", value(a), value(b));
+                    assert_eq!(value(sum), expected, "{context}");
+                    assert_eq!(
+                        circuit.get_output(adder.overflow),
+                        exact != expected,
+                        "{context}"
+                    );
+                }
+            }
+        }
+    }
+
+    fn num_nodes<C: CircuitSim>(circuit: &C) -> usize {
+        circuit.num_nodes()
+    }
+
+    #[test]
+    fn rca_num_nodes_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let (cin, _) = Connector::input(builder.clone());
+        let rca = RippleCarryAdder::<8>::new(builder.clone(), cin);
+        let mut borrow = builder.borrow_mut();
+        let (circuit, _) = borrow.build();
+        // Carry in, then per bit two inputs, a three input XOR for the sum and
+        // three ANDs into an OR for the carry
+        assert_eq!(num_nodes(circuit), 1 + 8 * (2 + 1 + 4));
+
+        circuit.delete_node(rca.cout);
+        assert_eq!(num_nodes(circuit), 1 + 8 * (2 + 1 + 4) - 1);
+    }
 }
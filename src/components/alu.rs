@@ -0,0 +1,171 @@
+use std::{cell::RefCell, sync::Arc};
+
+use crate::{
+    circuit_builder::{self, CircuitBuilder, NoHooks},
+    circuit_sim::{CircuitSim, NodeType},
+    Circuit, NodeId,
+};
+
+use super::{adder::RippleCarryAdder, mux::create_n_to_1_mux, wire::Wire};
+
+type Connector = circuit_builder::Connector<NoHooks>;
+
+/// Width of [Alu::opcode].
+pub const OP_BITS: usize = 3;
+
+/// The operations of an [Alu], with their opcodes. Opcodes past the last
+/// operation give 0.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AluOp {
+    Add,
+    Sub,
+    And,
+    Or,
+    Xor,
+}
+
+impl AluOp {
+    pub const ALL: [AluOp; 5] = [AluOp::Add, AluOp::Sub, AluOp::And, AluOp::Or, AluOp::Xor];
+}
+
+/// Arithmetic logic unit applying the [AluOp] in `opcode` to `input_a` and
+/// `input_b`. `carry_out` is the carry of [AluOp::Add], and for
+/// [AluOp::Sub] is set when there was no borrow, i.e. `a >= b` unsigned.
+/// It's low for the logic operations. `zero` is set when the result is 0.
+pub struct Alu<const BITS: usize> {
+    pub input_a: Wire<BITS>,
+    pub input_b: Wire<BITS>,
+    pub opcode: Wire<OP_BITS>,
+    pub result: Wire<BITS>,
+    pub carry_out: NodeId,
+    pub zero: NodeId,
+}
+
+/// Each bit of `a` and `b` through a two input gate of `node_type`.
+fn bitwise<const BITS: usize>(
+    circuit: &mut Circuit,
+    node_type: NodeType,
+    a: Wire<BITS>,
+    b: Wire<BITS>,
+) -> Wire<BITS> {
+    Wire::of_node_ids(|bit| {
+        let output = circuit.create_node(node_type);
+        circuit.connect(a[bit], output);
+        circuit.connect(b[bit], output);
+        output
+    })
+}
+
+impl<const BITS: usize> Alu<BITS> {
+    pub fn new(builder: Arc<RefCell<CircuitBuilder>>) -> Self {
+        let rca = RippleCarryAdder::<BITS>::new(
+            builder.clone(),
+            Connector::input_ignore(builder.clone()),
+        );
+        let circuit = &mut builder.borrow_mut().circuit;
+        let input_a = Wire::new(circuit);
+        let input_b = Wire::new(circuit);
+        let opcode = Wire::<OP_BITS>::new(circuit);
+        let decoded = opcode.decode::<{ AluOp::ALL.len() }>(circuit);
+
+        // Subtracting adds the inverse of b plus one
+        let sub = decoded[AluOp::Sub as usize];
+        circuit.connect(sub, rca.cin);
+        for bit in 0..BITS {
+            circuit.connect(input_a[bit], rca.input_a[bit]);
+            let b = circuit.create_node(NodeType::Xor);
+            circuit.connect(input_b[bit], b);
+            circuit.connect(sub, b);
+            circuit.connect(b, rca.input_b[bit]);
+        }
+        let sum = Wire::of_node_ids(|bit| rca.sum[bit]);
+        let results = AluOp::ALL.map(|op| match op {
+            AluOp::Add | AluOp::Sub => sum,
+            AluOp::And => bitwise(circuit, NodeType::And, input_a, input_b),
+            AluOp::Or => bitwise(circuit, NodeType::Or, input_a, input_b),
+            AluOp::Xor => bitwise(circuit, NodeType::Xor, input_a, input_b),
+        });
+        let result = create_n_to_1_mux(circuit, results, opcode);
+
+        let arithmetic = circuit.create_node(NodeType::Or);
+        circuit.connect(decoded[AluOp::Add as usize], arithmetic);
+        circuit.connect(sub, arithmetic);
+        let carry_out = circuit.create_node(NodeType::And);
+        circuit.connect(rca.cout, carry_out);
+        circuit.connect(arithmetic, carry_out);
+        let zero = result.fold_gate(circuit, NodeType::Nor);
+
+        Self {
+            input_a,
+            input_b,
+            opcode,
+            result,
+            carry_out,
+            zero,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, sync::Arc};
+
+    use rand::Rng;
+
+    use crate::{circuit_builder::CircuitBuilder, circuit_sim::CircuitSim, test_utils::seeded_rng};
+
+    use super::{Alu, AluOp};
+
+    #[test]
+    fn alu_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let alu = Alu::<8>::new(builder.clone());
+        let mut borrow = builder.borrow_mut();
+        let (circuit, _) = borrow.build();
+        let mut rng = seeded_rng();
+
+        for op in AluOp::ALL {
+            alu.opcode.set(circuit, op as u8);
+            let mut operands: Vec<(u8, u8)> = (0..64).map(|_| rng.gen()).collect();
+            // Make sure every flag gets set
+            operands.extend([
+                (0, 0),
+                (0xFF, 0xFF),
+                (0x80, 0x80),
+                (0x5A, 0x5A),
+                (0x0F, 0xF0),
+            ]);
+            for (a, b) in operands {
+                alu.input_a.set(circuit, a);
+                alu.input_b.set(circuit, b);
+                circuit.run_until_done();
+                let (expected, carry) = match op {
+                    AluOp::Add => a.overflowing_add(b),
+                    AluOp::Sub => (a.wrapping_sub(b), a >= b),
+                    AluOp::And => (a & b, false),
+                    AluOp::Or => (a | b, false),
+                    AluOp::Xor => (a ^ b, false),
+                };
+                let result = alu.result.read::<u8>(circuit);
+                assert_eq!(result, expected, "{a:#x} {op:?} {b:#x}");
+                assert_eq!(
+                    circuit.get_output(alu.carry_out),
+                    carry,
+                    "{a:#x} {op:?} {b:#x}"
+                );
+                assert_eq!(
+                    circuit.get_output(alu.zero),
+                    expected == 0,
+                    "{a:#x} {op:?} {b:#x}"
+                );
+            }
+        }
+
+        // Unused opcodes give 0
+        alu.opcode.set(circuit, 7u8);
+        circuit.run_until_done();
+        assert_eq!(alu.result.read::<u8>(circuit), 0);
+        assert!(circuit.get_output(alu.zero));
+    }
+}
@@ -0,0 +1,78 @@
+use crate::{
+    circuit_sim::{CircuitSim, NodeType},
+    Circuit,
+};
+
+use super::wire::Wire;
+
+/// Counts the zero bits above the most significant set bit of `input`, or
+/// outputs `IN` if no bit is set. Every output settles within three ticks,
+/// at the cost of a quadratic number of connections.
+pub fn count_leading_zeros<const IN: usize, const OUT: usize>(
+    circuit: &mut Circuit,
+    input: Wire<IN>,
+) -> Wire<OUT> {
+    assert!(IN < (1 << OUT), "{OUT} bits can't count up to {IN}");
+
+    // Bit `i` is the most significant set bit if every bit above it is low
+    let first_set: Vec<_> = (0..IN)
+        .map(|i| {
+            if i + 1 == IN {
+                return input[i];
+            }
+            let above_clear = circuit.create_node(NodeType::Nor);
+            for bit in i + 1..IN {
+                circuit.connect(input[bit], above_clear);
+            }
+            let first_set = circuit.create_node(NodeType::And);
+            circuit.connect(input[i], first_set);
+            circuit.connect(above_clear, first_set);
+            first_set
+        })
+        .collect();
+    let all_clear = circuit.create_node(NodeType::Nor);
+    for bit in input.iter().cloned() {
+        circuit.connect(bit, all_clear);
+    }
+
+    Wire::of_node_ids(|out_bit| {
+        let output = circuit.create_node(NodeType::Or);
+        for (i, first_set) in first_set.iter().cloned().enumerate() {
+            if (IN - 1 - i) & (1 << out_bit) != 0 {
+                circuit.connect(first_set, output);
+            }
+        }
+        if IN & (1 << out_bit) != 0 {
+            circuit.connect(all_clear, output);
+        }
+        output
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+
+    use crate::{circuit_sim::CircuitSim, components::wire::Wire, test_utils::seeded_rng, Circuit};
+
+    use super::count_leading_zeros;
+
+    #[test]
+    fn count_leading_zeros_test() {
+        let mut circuit = Circuit::new();
+        let input = Wire::<16>::new(&mut circuit);
+        let output = count_leading_zeros::<16, 5>(&mut circuit, input);
+
+        let mut rng = seeded_rng();
+        let vals = [0, 1, 0x8000, u16::MAX].into_iter();
+        for val in vals.chain((0..100).map(|_| rng.gen::<u16>() >> rng.gen_range(0..16))) {
+            input.set(&mut circuit, val);
+            circuit.run_until_done();
+            assert_eq!(
+                output.read::<u32>(&circuit),
+                val.leading_zeros(),
+                "{val:#018b}"
+            );
+        }
+    }
+}
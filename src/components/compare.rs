@@ -0,0 +1,93 @@
+use crate::{
+    circuit_sim::{CircuitSim, NodeType},
+    Circuit, NodeId,
+};
+
+use super::wire::Wire;
+
+/// The outputs of [compare], exactly one of which is high.
+#[derive(Clone, Copy, Debug)]
+pub struct Comparator {
+    pub eq: NodeId,
+    pub lt: NodeId,
+    pub gt: NodeId,
+}
+
+/// Compares `a` and `b` as unsigned numbers. Going from the most
+/// significant bit down, `a` is greater at the first bit where it's high
+/// and `b` is low, as long as every bit above was equal.
+pub fn compare<const BITS: usize>(
+    circuit: &mut Circuit,
+    a: Wire<BITS>,
+    b: Wire<BITS>,
+) -> Comparator {
+    let same = Wire::<BITS>::of_node_ids(|bit| {
+        let same = circuit.create_node(NodeType::Xnor);
+        circuit.connect(a[bit], same);
+        circuit.connect(b[bit], same);
+        same
+    });
+    let eq = same.reduce_and(circuit);
+
+    let lt = circuit.create_node(NodeType::Or);
+    let gt = circuit.create_node(NodeType::Or);
+    // Whether every bit above the current one is equal, none for the top
+    let mut above_eq: Option<NodeId> = None;
+    for bit in (0..BITS).rev() {
+        for (high, low, output) in [(a[bit], b[bit], gt), (b[bit], a[bit], lt)] {
+            let low_not = circuit.create_node(NodeType::Not);
+            circuit.connect(low, low_not);
+            let wins = circuit.create_node(NodeType::And);
+            circuit.connect(high, wins);
+            circuit.connect(low_not, wins);
+            if let Some(above_eq) = above_eq {
+                circuit.connect(above_eq, wins);
+            }
+            circuit.connect(wins, output);
+        }
+        above_eq = Some(match above_eq {
+            Some(above_eq) => {
+                let still_eq = circuit.create_node(NodeType::And);
+                circuit.connect(above_eq, still_eq);
+                circuit.connect(same[bit], still_eq);
+                still_eq
+            }
+            None => same[bit],
+        });
+    }
+
+    Comparator { eq, lt, gt }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+
+    use crate::{circuit_sim::CircuitSim, components::wire::Wire, test_utils::seeded_rng, Circuit};
+
+    use super::compare;
+
+    #[test]
+    fn compare_test() {
+        let mut circuit = Circuit::default();
+        let a = Wire::<8>::new(&mut circuit);
+        let b = Wire::<8>::new(&mut circuit);
+        let comparator = compare(&mut circuit, a, b);
+        let mut rng = seeded_rng();
+
+        let mut pairs: Vec<(u8, u8)> = (0..256).map(|_| rng.gen()).collect();
+        pairs.extend((0..16).map(|_| {
+            let val = rng.gen();
+            (val, val)
+        }));
+        pairs.extend([(0, 0), (0x80, 0x7F), (0x7F, 0x80), (0xFF, 0xFE)]);
+        for (x, y) in pairs {
+            a.set(&mut circuit, x);
+            b.set(&mut circuit, y);
+            circuit.run_until_done();
+            assert_eq!(circuit.get_output(comparator.eq), x == y, "{x} == {y}");
+            assert_eq!(circuit.get_output(comparator.lt), x < y, "{x} < {y}");
+            assert_eq!(circuit.get_output(comparator.gt), x > y, "{x} > {y}");
+        }
+    }
+}
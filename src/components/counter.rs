@@ -0,0 +1,129 @@
+use std::{cell::RefCell, sync::Arc};
+
+use crate::{
+    circuit_builder::{self, CircuitBuilder, NoHooks},
+    circuit_sim::{CircuitSim, NodeType},
+    NodeId,
+};
+
+use super::{adder::RippleCarryAdder, register::Register, wire::Wire};
+
+type Connector = circuit_builder::Connector<NoHooks>;
+
+/// Synchronous binary up-counter. On the rising edge of `clk` it goes to 0
+/// if `reset` is set, otherwise loads `load_input` if `load` is set,
+/// otherwise counts up by one if `enable` is set, wrapping around at the
+/// top. Starts out at 0. The controls need to settle before the edge, like
+/// with [Register].
+pub struct Counter<const BITS: usize> {
+    pub clk: NodeId,
+    pub reset: NodeId,
+    pub enable: NodeId,
+    pub load: NodeId,
+    pub load_input: Wire<BITS>,
+    pub output: Wire<BITS>,
+}
+
+impl<const BITS: usize> Counter<BITS> {
+    pub fn new(builder: Arc<RefCell<CircuitBuilder>>) -> Self {
+        // Adding `enable` as the carry in makes the sum the next count
+        let rca = RippleCarryAdder::<BITS>::new(
+            builder.clone(),
+            Connector::input_ignore(builder.clone()),
+        );
+        let circuit = &mut builder.borrow_mut().circuit;
+        let register = Register::<BITS>::new(circuit);
+        let reset = circuit.create_input();
+        let load = circuit.create_input();
+        let load_input = Wire::new(circuit);
+        let keep = circuit.create_node(NodeType::Not);
+        circuit.connect(reset, keep);
+        let count = circuit.create_node(NodeType::Not);
+        circuit.connect(load, count);
+
+        for bit in 0..BITS {
+            circuit.connect(register.output[bit], rca.input_a[bit]);
+            let loaded = circuit.create_node(NodeType::And);
+            circuit.connect(load, loaded);
+            circuit.connect(load_input[bit], loaded);
+            let counted = circuit.create_node(NodeType::And);
+            circuit.connect(count, counted);
+            circuit.connect(rca.sum[bit], counted);
+            let chosen = circuit.create_node(NodeType::Or);
+            circuit.connect(loaded, chosen);
+            circuit.connect(counted, chosen);
+            let next = circuit.create_node(NodeType::And);
+            circuit.connect(keep, next);
+            circuit.connect(chosen, next);
+            circuit.connect(next, register.input[bit]);
+        }
+        for control in [reset, load, rca.cin] {
+            circuit.connect(control, register.enable);
+        }
+
+        Self {
+            clk: register.clk,
+            reset,
+            enable: rca.cin,
+            load,
+            load_input,
+            output: register.output,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, sync::Arc};
+
+    use crate::{circuit_builder::CircuitBuilder, circuit_sim::CircuitSim, Circuit, NodeId};
+
+    use super::Counter;
+
+    #[test]
+    fn counter_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let counter = Counter::<4>::new(builder.clone());
+        let mut borrow = builder.borrow_mut();
+        let (circuit, _) = borrow.build();
+        circuit.run_until_done();
+        assert_eq!(counter.output.read::<u8>(circuit), 0);
+
+        // Sets the controls, then clocks once they've settled
+        let step = |circuit: &mut Circuit, controls: [(NodeId, bool); 3]| {
+            for (control, val) in controls {
+                circuit.set_input(control, val);
+            }
+            circuit.run_until_done();
+            circuit.set_input(counter.clk, true);
+            circuit.run_until_done();
+            circuit.set_input(counter.clk, false);
+            circuit.run_until_done();
+            counter.output.read::<u8>(circuit)
+        };
+        let controls = |reset, load, enable| {
+            [
+                (counter.reset, reset),
+                (counter.load, load),
+                (counter.enable, enable),
+            ]
+        };
+
+        // Enable counts up and wraps around
+        for expected in (1..16).chain([0, 1]) {
+            assert_eq!(step(circuit, controls(false, false, true)), expected);
+        }
+        // Nothing set holds
+        assert_eq!(step(circuit, controls(false, false, false)), 1);
+        // Load wins over enable
+        counter.load_input.set(circuit, 0xAu8);
+        assert_eq!(step(circuit, controls(false, true, true)), 0xA);
+        assert_eq!(step(circuit, controls(false, false, true)), 0xB);
+        // Reset wins over everything
+        assert_eq!(step(circuit, controls(true, true, true)), 0);
+        assert_eq!(step(circuit, controls(false, false, true)), 1);
+        counter.load_input.set(circuit, 0x6u8);
+        assert_eq!(step(circuit, controls(false, true, false)), 0x6);
+        assert_eq!(step(circuit, controls(true, false, false)), 0);
+    }
+}
@@ -0,0 +1,169 @@
+use std::ops::Shl;
+
+use num_traits::Unsigned;
+
+use crate::{
+    circuit_sim::{CircuitSim, NodeType},
+    Circuit, NodeId,
+};
+
+use super::{memory::create_d_flip_flop2, wire::Wire};
+
+/// JK flip-flop on the rising edge of `clock`: holds with neither `j` nor
+/// `k` set, sets with just `j`, resets with just `k` and toggles with both.
+/// A [create_d_flip_flop2] fed the next state `j & !q | !k & q`, so it
+/// starts out holding 0 the same way.
+pub fn create_jk_flip_flop(circuit: &mut Circuit, j: NodeId, k: NodeId, clock: NodeId) -> NodeId {
+    let next = circuit.create_node(NodeType::Or);
+    let q = create_d_flip_flop2(circuit, next, clock);
+
+    let q_not = circuit.create_node(NodeType::Not);
+    circuit.connect(q, q_not);
+    let set = circuit.create_node(NodeType::And);
+    circuit.connect(j, set);
+    circuit.connect(q_not, set);
+
+    let k_not = circuit.create_node(NodeType::Not);
+    circuit.connect(k, k_not);
+    let hold = circuit.create_node(NodeType::And);
+    circuit.connect(k_not, hold);
+    circuit.connect(q, hold);
+
+    circuit.connect(set, next);
+    circuit.connect(hold, next);
+    q
+}
+
+/// T flip-flop on the rising edge of `clock`: toggles while `t` is set and
+/// holds otherwise. A [create_d_flip_flop2] fed `t ^ q`, starting at 0.
+pub fn create_t_flip_flop(circuit: &mut Circuit, t: NodeId, clock: NodeId) -> NodeId {
+    let next = circuit.create_node(NodeType::Xor);
+    let q = create_d_flip_flop2(circuit, next, clock);
+    circuit.connect(t, next);
+    circuit.connect(q, next);
+    q
+}
+
+/// Counter that goes up by one on every rising edge of `clk`, wrapping
+/// around. Each bit is a toggling T flip-flop clocked by the inverse of the
+/// bit below, so it toggles when that bit falls back to 0. The carry
+/// ripples through the bits one flip-flop at a time, so the count is only
+/// right once the circuit settles.
+pub struct RippleCounter<const BITS: usize> {
+    /// The bits of the count, least significant first.
+    pub bits: [NodeId; BITS],
+    pub clk: NodeId,
+}
+
+impl<const BITS: usize> RippleCounter<BITS> {
+    pub fn new(circuit: &mut Circuit) -> Self {
+        assert!(BITS > 0);
+        let clk = circuit.create_input();
+        let toggle = circuit.constant(true);
+        let mut clock = clk;
+        let bits = [(); BITS].map(|_| {
+            let bit = create_t_flip_flop(circuit, toggle, clock);
+            clock = circuit.create_node(NodeType::Not);
+            circuit.connect(bit, clock);
+            bit
+        });
+        Self { bits, clk }
+    }
+
+    pub fn read<T>(&self, circuit: &Circuit) -> T
+    where
+        T: Unsigned + Shl<usize, Output = T> + std::fmt::Debug,
+    {
+        Wire::<BITS>::of_node_ids(|bit| self.bits[bit]).read(circuit)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{circuit_sim::CircuitSim, Circuit};
+
+    use super::{create_jk_flip_flop, create_t_flip_flop, RippleCounter};
+
+    #[test]
+    fn t_flip_flop_test() {
+        let mut circuit = Circuit::default();
+        let t = circuit.create_input();
+        let clock = circuit.create_input();
+        let q = create_t_flip_flop(&mut circuit, t, clock);
+        circuit.run_until_done();
+
+        let mut expected = false;
+        for t_val in [false, true, true, false, true] {
+            circuit.set_input(t, t_val);
+            circuit.run_until_done();
+            circuit.set_input(clock, true);
+            circuit.run_until_done();
+            expected ^= t_val;
+            assert_eq!(circuit.get_output(q), expected, "t {t_val}");
+            circuit.set_input(clock, false);
+            circuit.run_until_done();
+            assert_eq!(circuit.get_output(q), expected, "held on the falling edge");
+        }
+    }
+
+    #[test]
+    fn ripple_counter_test() {
+        let mut circuit = Circuit::default();
+        let counter = RippleCounter::<4>::new(&mut circuit);
+        circuit.run_until_done();
+        assert_eq!(counter.read::<u8>(&circuit), 0);
+
+        for count in 1..=20 {
+            circuit.set_input(counter.clk, true);
+            circuit.run_until_done();
+            assert_eq!(counter.read::<u8>(&circuit), count % 16);
+            circuit.set_input(counter.clk, false);
+            circuit.run_until_done();
+            assert_eq!(counter.read::<u8>(&circuit), count % 16);
+        }
+    }
+
+    #[test]
+    fn jk_flip_flop_test() {
+        // Starting state, then the state after each of two rising edges
+        let cases = [
+            ((false, false), false, [false, false]),
+            ((false, false), true, [true, true]),
+            ((true, false), false, [true, true]),
+            ((true, false), true, [true, true]),
+            ((false, true), false, [false, false]),
+            ((false, true), true, [false, false]),
+            ((true, true), false, [true, false]),
+            ((true, true), true, [false, true]),
+        ];
+        for ((j_val, k_val), start, expected) in cases {
+            let mut circuit = Circuit::default();
+            let j = circuit.create_input();
+            let k = circuit.create_input();
+            let clock = circuit.create_input();
+            let q = create_jk_flip_flop(&mut circuit, j, k, clock);
+            circuit.run_until_done();
+            assert!(!circuit.get_output(q), "starts at 0");
+
+            let edge = |circuit: &mut Circuit| {
+                circuit.set_input(clock, true);
+                circuit.run_until_done();
+                circuit.set_input(clock, false);
+                circuit.run_until_done();
+                circuit.get_output(q)
+            };
+            if start {
+                circuit.set_input(j, true);
+                circuit.run_until_done();
+                assert!(edge(&mut circuit));
+                circuit.set_input(j, false);
+            }
+
+            circuit.set_input(j, j_val);
+            circuit.set_input(k, k_val);
+            circuit.run_until_done();
+            let states = [edge(&mut circuit), edge(&mut circuit)];
+            assert_eq!(states, expected, "j {j_val}, k {k_val} from {start}");
+        }
+    }
+}
@@ -1,5 +1,7 @@
+use std::{cell::RefCell, sync::Arc};
+
 use crate::{
-    circuit_builder::{ops::*, BuilderHooks, Connector},
+    circuit_builder::{ops::*, BuilderHooks, CircuitBuilderWithHooks, Connector},
     circuit_sim::{
         CircuitSim,
         NodeType::{self, *},
@@ -13,12 +15,21 @@ use super::wire::Wire;
 // - Revamp Connector interface again
 //   - Either make everything use it or find a better way to construct gates
 //     without it
-// - More efficient latches (shared input_not?)
-//   - Shared input_not?
+// - More efficient latches
 //   - Multi-dimensional cell array
 // - Test out uninitialized arrays for wires and other
 
-pub fn create_d_latch<T: BuilderHooks>(input: Connector<T>, enable: Connector<T>) -> Connector<T> {
+pub struct DLatch<T: BuilderHooks> {
+    pub q: Connector<T>,
+    pub q_not: Connector<T>,
+    pub input: Connector<T>,
+    pub enable: Connector<T>,
+}
+
+pub fn create_d_latch_full<T: BuilderHooks>(
+    input: Connector<T>,
+    enable: Connector<T>,
+) -> DLatch<T> {
     // TODO: Share input and input_not
 
     // All these [set]s are kinda hacks to initialize the latch as 0
@@ -33,7 +44,124 @@ pub fn create_d_latch<T: BuilderHooks>(input: Connector<T>, enable: Connector<T>
     q.set(false);
     q.connect(&q_not);
     q_not.connect(&q);
-    q
+    DLatch {
+        q,
+        q_not,
+        input,
+        enable,
+    }
+}
+
+pub fn create_d_latch<T: BuilderHooks>(input: Connector<T>, enable: Connector<T>) -> Connector<T> {
+    create_d_latch_full(input, enable).q
+}
+
+/// Rising edge triggered D flip-flop out of two latches, where the first
+/// one is open while the clock is low.
+pub fn create_d_flip_flop<T: BuilderHooks>(
+    input: Connector<T>,
+    clock: &Connector<T>,
+) -> Connector<T> {
+    let master = create_d_latch(input, clock.invert());
+    create_d_latch(master, or!(clock))
+}
+
+/// Chain of `stages` flip-flops sharing a clock, returning the output of
+/// each stage starting from the one `input` feeds. Every rising edge moves
+/// each bit one stage along.
+pub fn create_shift_register<T: BuilderHooks>(
+    input: Connector<T>,
+    clock: &Connector<T>,
+    stages: usize,
+) -> Vec<Connector<T>> {
+    assert!(stages > 0);
+    let mut outputs = vec![create_d_flip_flop(input, clock)];
+    for stage in 1..stages {
+        let input = or!(outputs[stage - 1]);
+        outputs.push(create_d_flip_flop(input, clock));
+    }
+    outputs
+}
+
+pub struct Fifo<T: BuilderHooks> {
+    /// The oldest word, only meaningful while `empty` is low.
+    pub head: Vec<Connector<T>>,
+    pub empty: Connector<T>,
+    pub full: Connector<T>,
+    /// Whether each slot holds a word, starting from the head.
+    pub valid: Vec<Connector<T>>,
+}
+
+/// First in first out queue of `depth` words as wide as `input`, out of
+/// [create_d_flip_flop]s. On every rising edge of `clock` the head word is
+/// dropped if `pop` is high, and then `input` is added behind the rest if
+/// `push` is high and there's room, so a full queue only takes a push
+/// along with a pop.
+pub fn create_fifo<T: BuilderHooks>(
+    builder: Arc<RefCell<CircuitBuilderWithHooks<T>>>,
+    input: Vec<Connector<T>>,
+    push: Connector<T>,
+    pop: Connector<T>,
+    clock: &Connector<T>,
+    depth: usize,
+) -> Fifo<T> {
+    assert!(depth > 0);
+    assert!(!input.is_empty());
+    builder.borrow_mut().begin_module("fifo");
+    // Every slot's next state depends on its neighbours, so the flip-flops
+    // are fed from gates that get their inputs once all of them exist
+    let flip_flop = || {
+        let next = Connector::new(builder.clone());
+        let q = create_d_flip_flop(or!(next), clock);
+        (next, q)
+    };
+    let valid: Vec<_> = (0..depth).map(|_| flip_flop()).collect();
+    let data: Vec<Vec<_>> = (0..depth)
+        .map(|_| input.iter().map(|_| flip_flop()).collect())
+        .collect();
+
+    let pop_not = pop.invert();
+    let push_to = |slot: &Connector<T>, prev_slot: Option<&Connector<T>>| match prev_slot {
+        Some(prev_slot) => and!(push, slot.invert(), prev_slot),
+        None => and!(push, slot.invert()),
+    };
+    // Each slot after popping, then the first free one takes the input
+    let mut prev_shifted = None;
+    for i in 0..depth {
+        let shifted = match valid.get(i + 1) {
+            Some((_, after)) => or!(and!(pop, after), and!(pop_not, valid[i].1)),
+            None => and!(pop_not, valid[i].1),
+        };
+        let target = push_to(&shifted, prev_shifted.as_ref());
+        let target_not = target.invert();
+        for (bit, (next, q)) in data[i].iter().enumerate() {
+            let shifted_bit = match data.get(i + 1) {
+                Some(after) => or!(and!(pop, after[bit].1), and!(pop_not, q)),
+                None => or!(q),
+            };
+            and!(target, input[bit]).connect(next);
+            and!(target_not, shifted_bit).connect(next);
+        }
+        shifted.connect(&valid[i].0);
+        target.connect(&valid[i].0);
+        prev_shifted = Some(shifted);
+    }
+
+    let empty = valid[0].1.invert();
+    let full = or!(valid[depth - 1].1);
+    builder.borrow_mut().end_module();
+    Fifo {
+        head: data
+            .into_iter()
+            .next()
+            .unwrap()
+            .into_iter()
+            .map(|(_, q)| q)
+            .collect(),
+        empty,
+        full,
+        valid: valid.into_iter().map(|(_, q)| q).collect(),
+    }
 }
 
 pub fn create_d_latch2(
@@ -43,6 +171,18 @@ pub fn create_d_latch2(
     enable: NodeId,
     write: NodeId,
 ) -> NodeId {
+    create_d_latch2_full(circuit, input_pos, input_neg, enable, write).1
+}
+
+/// Like [create_d_latch2], but also returns the stored bit `q` alongside the
+/// enabled output, as `(q, output)`.
+pub fn create_d_latch2_full(
+    circuit: &mut Circuit,
+    input_pos: NodeId,
+    input_neg: NodeId,
+    enable: NodeId,
+    write: NodeId,
+) -> (NodeId, NodeId) {
     let q_reset = circuit.create_node(And);
     circuit.connect(input_neg, q_reset);
     circuit.connect(enable, q_reset);
@@ -68,7 +208,31 @@ pub fn create_d_latch2(
     let output = circuit.create_node(And);
     circuit.connect(q, output);
     circuit.connect(enable, output);
-    output
+    (q, output)
+}
+
+/// Like [create_d_flip_flop], built straight on a [Circuit] out of two
+/// [create_d_latch2] latches. Captures `d` on the rising edge of `clock`
+/// and starts out holding 0.
+pub fn create_d_flip_flop2(circuit: &mut Circuit, d: NodeId, clock: NodeId) -> NodeId {
+    // Each latch takes both polarities of its input and enable, all one
+    // gate away so they change on the same tick
+    fn gate(circuit: &mut Circuit, node_type: NodeType, input: NodeId) -> NodeId {
+        let output = circuit.create_node(node_type);
+        circuit.connect(input, output);
+        output
+    }
+    let clock_pos = gate(circuit, Buffer, clock);
+    let clock_neg = gate(circuit, Not, clock);
+    let d_pos = gate(circuit, Buffer, d);
+    let d_neg = gate(circuit, Not, d);
+    let write = circuit.constant(true);
+    let (master, _) = create_d_latch2_full(circuit, d_pos, d_neg, clock_neg, write);
+
+    let master_pos = gate(circuit, Buffer, master);
+    let master_neg = gate(circuit, Not, master);
+    let (slave, _) = create_d_latch2_full(circuit, master_pos, master_neg, clock_pos, write);
+    slave
 }
 
 pub struct Sram<const ADDR_SIZE: usize, const WORD_SIZE: usize> {
@@ -76,25 +240,47 @@ pub struct Sram<const ADDR_SIZE: usize, const WORD_SIZE: usize> {
     pub input: Wire<WORD_SIZE>,
     pub output: Wire<WORD_SIZE>,
     pub write: NodeId,
+    /// The latched bits of each cell, indexed by address.
+    pub cells: Vec<Wire<WORD_SIZE>>,
+}
+
+/// Buffered and inverted copies of the input, shared by every cell so each
+/// one doesn't need its own.
+struct SramInput<const BITS: usize> {
+    pos: Wire<BITS>,
+    neg: Wire<BITS>,
+}
+
+impl<const BITS: usize> SramInput<BITS> {
+    fn new(circuit: &mut Circuit, input: Wire<BITS>) -> Self {
+        Self {
+            pos: input.buffer(circuit),
+            neg: input.invert(circuit),
+        }
+    }
 }
 
+/// Returns the latched bits of the cell and its output, which is only driven
+/// while the cell is enabled.
 fn create_sram_cell<const BITS: usize>(
     circuit: &mut Circuit,
-    input: Wire<BITS>,
+    input: &SramInput<BITS>,
     enable: NodeId,
     write: NodeId,
-) -> Wire<BITS> {
-    input.map(|input| {
-        let input_pos = circuit.create_node(Or);
-        let input_neg = circuit.create_node(Nor);
-        circuit.connect(input, input_pos);
-        circuit.connect(input, input_neg);
-        create_d_latch2(circuit, input_pos, input_neg, enable, write)
-    })
+) -> (Wire<BITS>, Wire<BITS>) {
+    let mut cell = [NodeId::default(); BITS];
+    let output = Wire::of_node_ids(|bit| {
+        let (q, output) =
+            create_d_latch2_full(circuit, input.pos[bit], input.neg[bit], enable, write);
+        cell[bit] = q;
+        output
+    });
+    (Wire::of_node_ids(|bit| cell[bit]), output)
 }
 
 impl Sram<16, 16> {
     pub fn new<const CELLS: usize>(circuit: &mut Circuit) -> Self {
+        circuit.begin_module("sram");
         let address = Wire::new(circuit);
         let input = Wire::new(circuit);
 
@@ -107,20 +293,27 @@ impl Sram<16, 16> {
 
         let enables = address.decode::<CELLS>(circuit);
         let output = Wire::new(circuit);
+        let shared = SramInput::new(circuit, input);
+        let mut cells = Vec::with_capacity(CELLS);
         for enable in enables.iter().cloned() {
-            create_sram_cell(circuit, input, enable, write_delay_2).connect(circuit, &output);
+            let (cell, cell_output) = create_sram_cell(circuit, &shared, enable, write_delay_2);
+            cell_output.connect(circuit, &output);
+            cells.push(cell);
         }
+        circuit.end_module();
 
         Self {
             address,
             input,
             output,
             write,
+            cells,
         }
     }
 
     pub fn new_full_2d(circuit: &mut Circuit) -> Self {
         const CELLS: usize = 1 << 16;
+        circuit.begin_module("sram");
         let address = Wire::new(circuit);
         let input = Wire::new(circuit);
 
@@ -137,6 +330,8 @@ impl Sram<16, 16> {
         let sel1 = address.slice::<8, 8>().decode::<256>(circuit);
 
         let output = Wire::new(circuit);
+        let shared = SramInput::new(circuit, input);
+        let mut cells = Vec::with_capacity(CELLS);
         for i in 0..CELLS {
             let i0 = i & ((1 << 8) - 1);
             let i1 = i >> 8;
@@ -144,14 +339,18 @@ impl Sram<16, 16> {
             circuit.connect(sel0[i0], enable);
             circuit.connect(sel1[i1], enable);
 
-            create_sram_cell(circuit, input, enable, write_delay_2).connect(circuit, &output);
+            let (cell, cell_output) = create_sram_cell(circuit, &shared, enable, write_delay_2);
+            cell_output.connect(circuit, &output);
+            cells.push(cell);
         }
+        circuit.end_module();
 
         Self {
             address,
             input,
             output,
             write,
+            cells,
         }
     }
 
@@ -170,6 +369,12 @@ impl Sram<16, 16> {
         circuit.run_until_done();
         self.output.read(circuit)
     }
+
+    /// Reads the bits stored in a cell straight from its latches, bypassing
+    /// the address decoding and output of [Self::get].
+    pub fn peek_cell(&self, circuit: &Circuit, address: u16) -> u16 {
+        self.cells[address as usize].read(circuit)
+    }
 }
 
 #[cfg(test)]
@@ -177,12 +382,12 @@ mod test {
     use std::{cell::RefCell, sync::Arc};
 
     use crate::{
-        circuit_builder::{CircuitBuilder, Connector},
+        circuit_builder::{ops::*, CircuitBuilder, Connector},
         circuit_sim::CircuitSim,
         Circuit,
     };
 
-    use super::{create_d_latch, Sram};
+    use super::{create_d_flip_flop2, create_d_latch, create_d_latch_full, create_fifo, Sram};
 
     #[test]
     fn d_latch_test() {
@@ -193,30 +398,140 @@ mod test {
         let circuit = &mut builder.borrow_mut().circuit;
 
         let output = circuit.get_output(output_id);
-        assert_eq!(output, false);
+        assert!(!output);
         circuit.run_until_done();
         let output = circuit.get_output(output_id);
-        assert_eq!(output, false);
+        assert!(!output);
 
         circuit.set_input(enable_id, true);
         circuit.set_input(input_id, true);
         circuit.run_until_done();
         let output = circuit.get_output(output_id);
-        assert_eq!(output, true);
+        assert!(output);
         circuit.set_input(enable_id, false);
         circuit.run_until_done();
         let output = circuit.get_output(output_id);
-        assert_eq!(output, true);
+        assert!(output);
 
         circuit.set_input(enable_id, true);
         circuit.set_input(input_id, false);
         circuit.run_until_done();
         let output = circuit.get_output(output_id);
-        assert_eq!(output, false);
+        assert!(!output);
         circuit.set_input(enable_id, false);
         circuit.run_until_done();
         let output = circuit.get_output(output_id);
-        assert_eq!(output, false);
+        assert!(!output);
+    }
+
+    #[test]
+    fn d_latch_full_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let (input_connector, input_id) = Connector::input(builder.clone());
+        let (enable_connector, enable_id) = Connector::input(builder.clone());
+        let (other_connector, other_id) = Connector::input(builder.clone());
+        let latch = create_d_latch_full(input_connector, enable_connector);
+        let downstream = and!(latch.q_not, other_connector);
+        let circuit = &mut builder.borrow_mut().circuit;
+
+        circuit.set_input(other_id, true);
+        for val in [true, false, true] {
+            circuit.set_input(enable_id, true);
+            circuit.set_input(input_id, val);
+            circuit.run_until_done();
+            circuit.set_input(enable_id, false);
+            circuit.set_input(input_id, !val);
+            circuit.run_until_done();
+            let q = circuit.get_output(latch.q.output);
+            let q_not = circuit.get_output(latch.q_not.output);
+            assert_eq!(q, val);
+            assert_eq!(q_not, !q);
+            assert_eq!(circuit.get_output(downstream.output), !val);
+        }
+    }
+
+    #[test]
+    fn d_flip_flop2_test() {
+        let mut circuit = Circuit::default();
+        let d = circuit.create_input();
+        let clock = circuit.create_input();
+        let q = create_d_flip_flop2(&mut circuit, d, clock);
+        circuit.run_until_done();
+        assert!(!circuit.get_output(q), "starts at 0");
+
+        for val in [true, false, true, true, false] {
+            circuit.set_input(d, val);
+            circuit.run_until_done();
+            circuit.set_input(clock, true);
+            circuit.run_until_done();
+            assert_eq!(circuit.get_output(q), val, "sampled on the rising edge");
+
+            // Changes while the clock is high or falling are ignored
+            circuit.set_input(d, !val);
+            circuit.run_until_done();
+            assert_eq!(circuit.get_output(q), val, "held while high");
+            circuit.set_input(clock, false);
+            circuit.run_until_done();
+            assert_eq!(circuit.get_output(q), val, "held on the falling edge");
+        }
+    }
+
+    #[test]
+    fn fifo_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let input: Vec<_> = (0..4).map(|_| Connector::input(builder.clone())).collect();
+        let (push, push_id) = Connector::input(builder.clone());
+        let (pop, pop_id) = Connector::input(builder.clone());
+        let (clock, clock_id) = Connector::input(builder.clone());
+        let input_ids: Vec<_> = input.iter().map(|(_, input_id)| *input_id).collect();
+        let input = input.into_iter().map(|(input, _)| input).collect();
+        let fifo = create_fifo(builder.clone(), input, push, pop, &clock, 3);
+        let circuit = &mut builder.borrow_mut().circuit;
+        circuit.run_until_done();
+
+        // Returns the head word, or None when empty, along with whether
+        // it's full
+        let mut step = |push: Option<u8>, pop: bool| {
+            circuit.set_input(push_id, push.is_some());
+            circuit.set_input(pop_id, pop);
+            for (bit, input_id) in input_ids.iter().enumerate() {
+                circuit.set_input(*input_id, push.unwrap_or(0) & (1 << bit) != 0);
+            }
+            circuit.run_until_done();
+            circuit.set_input(clock_id, true);
+            circuit.run_until_done();
+            circuit.set_input(clock_id, false);
+            circuit.run_until_done();
+
+            let head = fifo
+                .head
+                .iter()
+                .enumerate()
+                .map(|(bit, q)| (circuit.get_output(q.output) as u8) << bit)
+                .sum::<u8>();
+            let empty = circuit.get_output(fifo.empty.output);
+            (
+                if empty { None } else { Some(head) },
+                circuit.get_output(fifo.full.output),
+            )
+        };
+
+        assert_eq!(step(None, true), (None, false), "popping when empty");
+        assert_eq!(step(Some(5), false), (Some(5), false));
+        assert_eq!(step(Some(9), false), (Some(5), false));
+        assert_eq!(step(None, true), (Some(9), false));
+        assert_eq!(step(Some(12), true), (Some(12), false), "push and pop");
+        assert_eq!(step(Some(1), false), (Some(12), false));
+        assert_eq!(step(Some(2), false), (Some(12), true));
+        assert_eq!(step(Some(3), false), (Some(12), true), "pushing when full");
+        assert_eq!(
+            step(Some(4), true),
+            (Some(1), true),
+            "push and pop when full"
+        );
+        assert_eq!(step(None, true), (Some(2), false));
+        assert_eq!(step(None, true), (Some(4), false));
+        assert_eq!(step(None, true), (None, false));
     }
 
     #[test]
@@ -244,4 +559,20 @@ mod test {
         assert_eq!(sram.get(&mut circuit, 67), 50);
         assert_eq!(sram.get(&mut circuit, 68), 100);
     }
+
+    #[test]
+    fn sram_peek_cell_test() {
+        let mut circuit = Circuit::default();
+        let sram = Sram::new::<64>(&mut circuit);
+
+        sram.set(&mut circuit, 12, 5);
+        sram.set(&mut circuit, 42, 18);
+        assert_eq!(sram.peek_cell(&circuit, 12), 5);
+        assert_eq!(sram.peek_cell(&circuit, 42), 18);
+        assert_eq!(sram.peek_cell(&circuit, 13), 0);
+        for address in [12, 42, 13] {
+            let peeked = sram.peek_cell(&circuit, address);
+            assert_eq!(sram.get(&mut circuit, address), peeked);
+        }
+    }
 }
@@ -1,4 +1,11 @@
 pub mod adder;
+pub mod alu;
+pub mod clz;
+pub mod compare;
+pub mod counter;
+pub mod flipflop;
 pub mod memory;
 pub mod mux;
+pub mod register;
+pub mod shift;
 pub mod wire;
@@ -15,3 +15,43 @@ pub fn create_n_to_1_mux<const BITS: usize, const N: usize, const SELECT_BITS: u
     }
     output
 }
+
+/// Routes `input` to the output picked by `select`, with every other
+/// output low. The inverse of [create_n_to_1_mux].
+pub fn create_1_to_n_demux<const BITS: usize, const N: usize, const SELECT_BITS: usize>(
+    circuit: &mut Circuit,
+    input: Wire<BITS>,
+    select: Wire<SELECT_BITS>,
+) -> [Wire<BITS>; N] {
+    assert!(N <= (1 << SELECT_BITS));
+    let decoded = select.decode::<N>(circuit);
+    std::array::from_fn(|i| input.enable(circuit, decoded[i]))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{circuit_sim::CircuitSim, components::wire::Wire, Circuit};
+
+    use super::{create_1_to_n_demux, create_n_to_1_mux};
+
+    #[test]
+    fn demux_test() {
+        let mut circuit = Circuit::default();
+        let input = Wire::<8>::new(&mut circuit);
+        let select = Wire::<3>::new(&mut circuit);
+        let outputs: [_; 6] = create_1_to_n_demux(&mut circuit, input, select);
+        // Muxing the outputs back gives the input again
+        let muxed = create_n_to_1_mux(&mut circuit, outputs, select);
+
+        input.set(&mut circuit, 0xC3u8);
+        for selected in 0..outputs.len() {
+            select.set(&mut circuit, selected as u8);
+            circuit.run_until_done();
+            for (i, output) in outputs.iter().enumerate() {
+                let expected = if i == selected { 0xC3 } else { 0 };
+                assert_eq!(output.read::<u8>(&circuit), expected, "{i} of {selected}");
+            }
+            assert_eq!(muxed.read::<u8>(&circuit), 0xC3);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{
+    circuit_sim::{CircuitSim, NodeType},
+    Circuit, NodeId,
+};
+
+use super::{memory::create_d_flip_flop2, wire::Wire};
+
+/// Register that loads `input` on the rising edge of `clk` while `enable` is
+/// set, and holds its value otherwise. Starts out holding 0. Like with any
+/// flip-flop, `input` and `enable` need to settle before the edge.
+pub struct Register<const BITS: usize> {
+    pub input: Wire<BITS>,
+    pub output: Wire<BITS>,
+    pub clk: NodeId,
+    pub enable: NodeId,
+}
+
+impl<const BITS: usize> Register<BITS> {
+    pub fn new(circuit: &mut Circuit) -> Self {
+        let input = Wire::new(circuit);
+        let clk = circuit.create_input();
+        let enable = circuit.create_input();
+        let hold = circuit.create_node(NodeType::Not);
+        circuit.connect(enable, hold);
+
+        // Each flip-flop is fed either the input or its own output
+        let output = Wire::of_node_ids(|bit| {
+            let next = circuit.create_node(NodeType::Or);
+            let q = create_d_flip_flop2(circuit, next, clk);
+            let load = circuit.create_node(NodeType::And);
+            circuit.connect(input[bit], load);
+            circuit.connect(enable, load);
+            let keep = circuit.create_node(NodeType::And);
+            circuit.connect(q, keep);
+            circuit.connect(hold, keep);
+            circuit.connect(load, next);
+            circuit.connect(keep, next);
+            q
+        });
+
+        Self {
+            input,
+            output,
+            clk,
+            enable,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{circuit_sim::CircuitSim, Circuit};
+
+    use super::Register;
+
+    #[test]
+    fn register_test() {
+        let mut circuit = Circuit::default();
+        let register = Register::<8>::new(&mut circuit);
+        circuit.run_until_done();
+        assert_eq!(register.output.read::<u8>(&circuit), 0);
+
+        let clock = |circuit: &mut Circuit| {
+            circuit.set_input(register.clk, true);
+            circuit.run_until_done();
+            circuit.set_input(register.clk, false);
+            circuit.run_until_done();
+        };
+
+        register.input.set(&mut circuit, 0xA5u8);
+        circuit.set_input(register.enable, true);
+        circuit.run_until_done();
+        assert_eq!(register.output.read::<u8>(&circuit), 0, "loads on the edge");
+        clock(&mut circuit);
+        assert_eq!(register.output.read::<u8>(&circuit), 0xA5);
+
+        circuit.set_input(register.enable, false);
+        for val in [0x00u8, 0xFF, 0x3C] {
+            register.input.set(&mut circuit, val);
+            clock(&mut circuit);
+            assert_eq!(register.output.read::<u8>(&circuit), 0xA5, "holds");
+        }
+
+        circuit.set_input(register.enable, true);
+        circuit.run_until_done();
+        clock(&mut circuit);
+        assert_eq!(register.output.read::<u8>(&circuit), 0x3C);
+    }
+}
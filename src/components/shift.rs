@@ -0,0 +1,98 @@
+use crate::{
+    circuit_sim::{CircuitSim, NodeType},
+    Circuit, NodeId,
+};
+
+use super::{memory::create_d_flip_flop2, wire::Wire};
+
+/// Serial in, parallel out shift register. On every rising edge of `clk`
+/// the bits move one place towards the most significant and `serial_in`
+/// enters bit 0, unless `parallel_load` is set, in which case it loads
+/// `load_input` instead. Starts out holding 0. [super::memory::create_shift_register]
+/// is the [crate::circuit_builder::Connector] counterpart without loading.
+pub struct ShiftRegister<const BITS: usize> {
+    pub serial_in: NodeId,
+    pub clk: NodeId,
+    pub parallel_load: NodeId,
+    pub load_input: Wire<BITS>,
+    pub output: Wire<BITS>,
+}
+
+impl<const BITS: usize> ShiftRegister<BITS> {
+    pub fn new(circuit: &mut Circuit) -> Self {
+        assert!(BITS > 0);
+        let serial_in = circuit.create_input();
+        let clk = circuit.create_input();
+        let parallel_load = circuit.create_input();
+        let load_input = Wire::new(circuit);
+        let shift = circuit.create_node(NodeType::Not);
+        circuit.connect(parallel_load, shift);
+
+        let mut previous = serial_in;
+        let output = Wire::of_node_ids(|bit| {
+            let next = circuit.create_node(NodeType::Or);
+            let shifted = circuit.create_node(NodeType::And);
+            circuit.connect(previous, shifted);
+            circuit.connect(shift, shifted);
+            let loaded = circuit.create_node(NodeType::And);
+            circuit.connect(load_input[bit], loaded);
+            circuit.connect(parallel_load, loaded);
+            circuit.connect(shifted, next);
+            circuit.connect(loaded, next);
+            previous = create_d_flip_flop2(circuit, next, clk);
+            previous
+        });
+
+        Self {
+            serial_in,
+            clk,
+            parallel_load,
+            load_input,
+            output,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{circuit_sim::CircuitSim, Circuit};
+
+    use super::ShiftRegister;
+
+    fn clock(circuit: &mut Circuit, clk: crate::NodeId) {
+        circuit.run_until_done();
+        circuit.set_input(clk, true);
+        circuit.run_until_done();
+        circuit.set_input(clk, false);
+        circuit.run_until_done();
+    }
+
+    #[test]
+    fn shift_register_test() {
+        let mut circuit = Circuit::default();
+        let register = ShiftRegister::<8>::new(&mut circuit);
+        circuit.run_until_done();
+        assert_eq!(register.output.read::<u8>(&circuit), 0);
+
+        // The first bit in ends up as the most significant
+        let pattern = 0b1011_0010u8;
+        for i in (0..8).rev() {
+            circuit.set_input(register.serial_in, pattern & (1 << i) != 0);
+            clock(&mut circuit, register.clk);
+            let shifted_in = 8 - i;
+            let expected = (pattern as u32 >> i) & ((1 << shifted_in) - 1);
+            assert_eq!(register.output.read::<u32>(&circuit), expected);
+        }
+        assert_eq!(register.output.read::<u8>(&circuit), pattern);
+
+        register.load_input.set(&mut circuit, 0x5Au8);
+        circuit.set_input(register.parallel_load, true);
+        clock(&mut circuit, register.clk);
+        assert_eq!(register.output.read::<u8>(&circuit), 0x5A);
+
+        circuit.set_input(register.parallel_load, false);
+        circuit.set_input(register.serial_in, true);
+        clock(&mut circuit, register.clk);
+        assert_eq!(register.output.read::<u8>(&circuit), 0x5A << 1 | 1);
+    }
+}
@@ -25,7 +25,17 @@ impl<const BITS: usize> Wire<BITS> {
     }
 
     pub fn new(circuit: &mut Circuit) -> Self {
-        Self::of_node_ids(|_| circuit.create_node(NodeType::Or))
+        let node_ids = circuit.create_nodes(NodeType::Or, BITS);
+        Self::of_node_ids(|bit| node_ids[bit])
+    }
+
+    /// A wire of AND nodes, for buses with open-collector drivers like I2C.
+    /// Any driver connected into a bit can pull it low, and a bit no driver
+    /// pulls low reads high as if pulled up. [Wire::new] is the wired-OR
+    /// counterpart.
+    pub fn new_wired_and(circuit: &mut Circuit) -> Self {
+        let node_ids = circuit.create_nodes(NodeType::And, BITS);
+        Self::of_node_ids(|bit| node_ids[bit])
     }
 
     pub fn read<T>(&self, circuit: &Circuit) -> T
@@ -51,6 +61,29 @@ impl<const BITS: usize> Wire<BITS> {
         }
     }
 
+    /// Like [Wire::read], but with index 0 as the most significant bit.
+    pub fn read_be<T>(&self, circuit: &Circuit) -> T
+    where
+        T: Unsigned + Shl<usize, Output = T> + std::fmt::Debug,
+    {
+        self.reversed().read(circuit)
+    }
+
+    /// Like [Wire::set], but with index 0 as the most significant bit.
+    pub fn set_be<T>(&self, circuit: &mut Circuit, val: T)
+    where
+        T: Unsigned + Copy + BitAnd<T, Output = T> + Shl<usize, Output = T> + std::fmt::Debug,
+    {
+        self.reversed().set(circuit, val)
+    }
+
+    /// The same nodes in the opposite bit order. No gates are created.
+    pub fn reversed(&self) -> Wire<BITS> {
+        let mut wire = *self;
+        wire.0.reverse();
+        wire
+    }
+
     pub fn connect(&self, circuit: &mut Circuit, output: &Self) {
         for (input, output) in self.iter().cloned().zip(output.iter().cloned()) {
             circuit.connect(input, output);
@@ -64,7 +97,40 @@ impl<const BITS: usize> Wire<BITS> {
         wire
     }
 
-    pub fn iter(&self) -> std::slice::Iter<NodeId> {
+    /// Like [Wire::slice], but starting at an offset read from `offset` as
+    /// the circuit runs, so `(self >> offset) & mask`. Bits past the end of
+    /// the wire read as zero. Built as a barrel shifter with one stage of
+    /// muxes per bit of `offset`.
+    pub fn extract<const SEL: usize, const WIDTH: usize>(
+        &self,
+        circuit: &mut Circuit,
+        offset: Wire<SEL>,
+    ) -> Wire<WIDTH> {
+        assert!(WIDTH <= BITS);
+        let mut shifted = *self;
+        for (stage, select) in offset.iter().cloned().enumerate() {
+            let select_not = circuit.create_node(NodeType::Nor);
+            circuit.connect(select, select_not);
+            let shift = 1usize.checked_shl(stage as u32).unwrap_or(usize::MAX);
+            shifted = Wire::of_node_ids(|bit| {
+                let output = circuit.create_node(NodeType::Or);
+                let keep = circuit.create_node(NodeType::And);
+                circuit.connect(shifted[bit], keep);
+                circuit.connect(select_not, keep);
+                circuit.connect(keep, output);
+                if let Some(from) = bit.checked_add(shift).filter(|from| *from < BITS) {
+                    let moved = circuit.create_node(NodeType::And);
+                    circuit.connect(shifted[from], moved);
+                    circuit.connect(select, moved);
+                    circuit.connect(moved, output);
+                }
+                output
+            });
+        }
+        Wire::of_node_ids(|bit| shifted[bit])
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, NodeId> {
         self.0.iter()
     }
 
@@ -84,12 +150,68 @@ impl<const BITS: usize> Wire<BITS> {
         })
     }
 
+    /// Connects every bit into a single new gate of `node_type`, where
+    /// [Wire::map_gate] makes a gate per bit.
+    pub fn fold_gate(&self, circuit: &mut Circuit, node_type: NodeType) -> NodeId {
+        let output = circuit.create_node(node_type);
+        for input in self.iter().cloned() {
+            circuit.connect(input, output);
+        }
+        output
+    }
+
+    /// High when any bit is.
+    pub fn reduce_or(&self, circuit: &mut Circuit) -> NodeId {
+        self.fold_gate(circuit, NodeType::Or)
+    }
+
+    /// High when every bit is.
+    pub fn reduce_and(&self, circuit: &mut Circuit) -> NodeId {
+        self.fold_gate(circuit, NodeType::And)
+    }
+
     pub fn buffer(&self, circuit: &mut Circuit) -> Wire<BITS> {
-        self.map_gate(circuit, NodeType::Or)
+        self.map_gate(circuit, NodeType::Buffer)
     }
 
     pub fn invert(&self, circuit: &mut Circuit) -> Wire<BITS> {
-        self.map_gate(circuit, NodeType::Nor)
+        self.map_gate(circuit, NodeType::Not)
+    }
+
+    /// `self & mask` for a mask known while building. No gates are
+    /// created: bits the mask clears are tied low and the rest are the same
+    /// nodes as in `self`.
+    pub fn and_const<T>(&self, circuit: &mut Circuit, mask: T) -> Wire<BITS>
+    where
+        T: Unsigned + Copy + BitAnd<T, Output = T> + Shl<usize, Output = T> + std::fmt::Debug,
+    {
+        self.mask_const(circuit, mask, false)
+    }
+
+    /// `self | mask` for a mask known while building. Like
+    /// [Wire::and_const], bits the mask sets are tied high and the rest pass
+    /// through.
+    pub fn or_const<T>(&self, circuit: &mut Circuit, mask: T) -> Wire<BITS>
+    where
+        T: Unsigned + Copy + BitAnd<T, Output = T> + Shl<usize, Output = T> + std::fmt::Debug,
+    {
+        self.mask_const(circuit, mask, true)
+    }
+
+    /// Ties bits where `mask` is `tie` to a constant `tie`.
+    fn mask_const<T>(&self, circuit: &mut Circuit, mask: T, tie: bool) -> Wire<BITS>
+    where
+        T: Unsigned + Copy + BitAnd<T, Output = T> + Shl<usize, Output = T> + std::fmt::Debug,
+    {
+        let mut constant = None;
+        Wire::of_node_ids(|bit| {
+            let mask_bit = (mask & (T::one() << bit)) != T::zero();
+            if mask_bit == tie {
+                *constant.get_or_insert_with(|| circuit.constant(tie))
+            } else {
+                self[bit]
+            }
+        })
     }
 
     pub fn enable(&self, circuit: &mut Circuit, enable: NodeId) -> Wire<BITS> {
@@ -100,6 +222,51 @@ impl<const BITS: usize> Wire<BITS> {
         wire
     }
 
+    /// Adds one to the wire, returning the sum and the carry out, which is
+    /// set when the wire was all ones. Uses a chain of half adders, since
+    /// the second operand is constant.
+    pub fn increment(&self, circuit: &mut Circuit) -> (Wire<BITS>, NodeId) {
+        self.step(circuit, false)
+    }
+
+    /// Subtracts one from the wire, returning the difference and the
+    /// borrow out, which is set when the wire was all zeros.
+    pub fn decrement(&self, circuit: &mut Circuit) -> (Wire<BITS>, NodeId) {
+        self.step(circuit, true)
+    }
+
+    /// Bit `i` flips when all the lower bits are ones when incrementing, or
+    /// zeros when decrementing.
+    fn step(&self, circuit: &mut Circuit, decrement: bool) -> (Wire<BITS>, NodeId) {
+        assert!(BITS > 0);
+        let mut carry = None;
+        let wire = Wire::of_node_ids(|bit| {
+            let input = self[bit];
+            let Some(carry_in) = carry else {
+                let output = circuit.create_node(NodeType::Nor);
+                circuit.connect(input, output);
+                carry = Some(if decrement { output } else { input });
+                return output;
+            };
+            let output = circuit.create_node(NodeType::Xor);
+            circuit.connect(input, output);
+            circuit.connect(carry_in, output);
+
+            let carry_out = circuit.create_node(NodeType::And);
+            if decrement {
+                let input_not = circuit.create_node(NodeType::Nor);
+                circuit.connect(input, input_not);
+                circuit.connect(input_not, carry_out);
+            } else {
+                circuit.connect(input, carry_out);
+            }
+            circuit.connect(carry_in, carry_out);
+            carry = Some(carry_out);
+            output
+        });
+        (wire, carry.unwrap())
+    }
+
     pub fn decode<const OUTPUTS: usize>(&self, circuit: &mut Circuit) -> Wire<OUTPUTS> {
         assert!(OUTPUTS <= (1 << BITS));
         let wire_pos = self.buffer(circuit);
@@ -118,6 +285,19 @@ impl<const BITS: usize> Wire<BITS> {
         }
         wire
     }
+
+    /// Like [Wire::decode], but every output stays low while `enable` is.
+    pub fn decode_with_enable<const OUTPUTS: usize>(
+        &self,
+        circuit: &mut Circuit,
+        enable: NodeId,
+    ) -> Wire<OUTPUTS> {
+        let wire = self.decode(circuit);
+        for output in wire.0.iter().cloned() {
+            circuit.connect(enable, output);
+        }
+        wire
+    }
 }
 
 impl<const BITS: usize> Index<usize> for Wire<BITS> {
@@ -128,6 +308,43 @@ impl<const BITS: usize> Index<usize> for Wire<BITS> {
     }
 }
 
+/// How [Circuit::format_wire] displays a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Radix {
+    /// `0b` followed by every bit, most significant first.
+    Binary,
+    /// `0x` followed by lowercase digits, padded to the width of the wire.
+    Hex,
+    Unsigned,
+    /// Two's complement with the last node as the sign bit.
+    Signed,
+}
+
+impl Circuit {
+    /// Formats the outputs of `wire_nodes` as a number, with the first node
+    /// as the least significant bit like [Wire::read]. Wires can be up to
+    /// 128 bits wide.
+    pub fn format_wire(&self, wire_nodes: &[NodeId], fmt: Radix) -> String {
+        let bits = wire_nodes.len();
+        assert!(bits <= 128, "Can't format a {bits} bit wire");
+        let val = wire_nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node_id)| self.get_output(**node_id))
+            .fold(0u128, |val, (bit, _)| val | (1 << bit));
+        match fmt {
+            Radix::Binary => format!("0b{val:0bits$b}"),
+            Radix::Hex => format!("0x{val:0digits$x}", digits = bits.div_ceil(4)),
+            Radix::Unsigned => val.to_string(),
+            Radix::Signed if bits == 0 => "0".to_string(),
+            Radix::Signed => {
+                let shift = 128 - bits;
+                ((val << shift) as i128 >> shift).to_string()
+            }
+        }
+    }
+}
+
 pub trait Signed<T> {
     fn read_signed(&self, circuit: &Circuit) -> T;
     fn set_signed(&self, circuit: &mut Circuit, val: T);
@@ -156,27 +373,180 @@ read_signed!(isize, usize);
 
 #[cfg(test)]
 mod test {
-    use crate::Circuit;
+    use rand::Rng;
 
-    use super::Wire;
+    use crate::{
+        circuit_sim::{CircuitSim, NodeType},
+        test_utils::seeded_rng,
+        Circuit,
+    };
+
+    use super::{Radix, Wire};
 
     #[test]
     fn set_read_test() {
         let mut circuit = Circuit::default();
         let wire = Wire::<16>::new(&mut circuit);
 
-        assert_eq!(wire.read::<u16>(&mut circuit), 0, "uninitialized");
+        assert_eq!(wire.read::<u16>(&circuit), 0, "uninitialized");
 
         wire.set(&mut circuit, 1u16);
-        assert_eq!(wire.read::<u16>(&mut circuit), 1);
+        assert_eq!(wire.read::<u16>(&circuit), 1);
 
         wire.set(&mut circuit, 420u16);
-        assert_eq!(wire.read::<u16>(&mut circuit), 420);
+        assert_eq!(wire.read::<u16>(&circuit), 420);
 
         wire.set(&mut circuit, u16::MAX);
-        assert_eq!(wire.read::<u16>(&mut circuit), u16::MAX);
+        assert_eq!(wire.read::<u16>(&circuit), u16::MAX);
 
         wire.set(&mut circuit, u16::MAX as u32 + 1);
-        assert_eq!(wire.read::<u16>(&mut circuit), 0);
+        assert_eq!(wire.read::<u16>(&circuit), 0);
+    }
+
+    #[test]
+    fn big_endian_test() {
+        let mut circuit = Circuit::default();
+        let wire = Wire::<8>::new(&mut circuit);
+
+        wire.set_be(&mut circuit, 0b0000_0001u8);
+        assert_eq!(wire.read::<u8>(&circuit), 0b1000_0000);
+        assert_eq!(wire.read_be::<u8>(&circuit), 1);
+
+        wire.set_be(&mut circuit, 0b1100_1010u8);
+        assert_eq!(wire.read::<u8>(&circuit), 0b0101_0011);
+        assert_eq!(wire.reversed().read::<u8>(&circuit), 0b1100_1010);
+    }
+
+    #[test]
+    fn format_wire_test() {
+        let mut circuit = Circuit::default();
+        let wire = Wire::<8>::new(&mut circuit);
+        let nodes: Vec<_> = wire.iter().cloned().collect();
+
+        wire.set(&mut circuit, 0xffu8);
+        assert_eq!(circuit.format_wire(&nodes, Radix::Signed), "-1");
+        assert_eq!(circuit.format_wire(&nodes, Radix::Unsigned), "255");
+        assert_eq!(circuit.format_wire(&nodes, Radix::Hex), "0xff");
+        assert_eq!(circuit.format_wire(&nodes, Radix::Binary), "0b11111111");
+
+        wire.set(&mut circuit, 0x05u8);
+        assert_eq!(circuit.format_wire(&nodes, Radix::Signed), "5");
+        assert_eq!(circuit.format_wire(&nodes, Radix::Hex), "0x05");
+        assert_eq!(circuit.format_wire(&nodes, Radix::Binary), "0b00000101");
+        assert_eq!(circuit.format_wire(&nodes[..3], Radix::Signed), "-3");
+    }
+
+    #[test]
+    fn extract_test() {
+        let mut circuit = Circuit::default();
+        let wire = Wire::<16>::new(&mut circuit);
+        let offset = Wire::<4>::new(&mut circuit);
+        let window = wire.extract::<4, 4>(&mut circuit, offset);
+
+        let mut rng = seeded_rng();
+        for _ in 0..200 {
+            let (val, shift) = (rng.gen::<u16>(), rng.gen_range(0..16u8));
+            wire.set(&mut circuit, val);
+            offset.set(&mut circuit, shift);
+            circuit.run_until_done();
+            assert_eq!(
+                window.read::<u16>(&circuit),
+                (val >> shift) & 0xf,
+                "{val:#06x} >> {shift}"
+            );
+        }
+    }
+
+    #[test]
+    fn wired_and_test() {
+        let mut circuit = Circuit::default();
+        let bus = Wire::<1>::new_wired_and(&mut circuit);
+        let drivers = [(); 2].map(|_| Wire::<1>::new(&mut circuit));
+        for driver in drivers.iter() {
+            driver.set(&mut circuit, 1u8);
+            driver.connect(&mut circuit, &bus);
+        }
+        circuit.run_until_done();
+        assert_eq!(bus.read::<u8>(&circuit), 1, "released");
+
+        for (i, driver) in drivers.iter().enumerate() {
+            driver.set(&mut circuit, 0u8);
+            circuit.run_until_done();
+            assert_eq!(bus.read::<u8>(&circuit), 0, "driver {i} pulled low");
+            driver.set(&mut circuit, 1u8);
+            circuit.run_until_done();
+            assert_eq!(bus.read::<u8>(&circuit), 1, "released");
+        }
+    }
+
+    #[test]
+    fn mask_const_test() {
+        let mut circuit = Circuit::default();
+        let wire = Wire::<8>::new(&mut circuit);
+        let low = wire.and_const(&mut circuit, 0x0Fu8);
+        let high = wire.or_const(&mut circuit, 0xF0u8);
+        assert_eq!(circuit.num_nodes(), 8 + 2, "one constant each");
+
+        let mut rng = seeded_rng();
+        for _ in 0..16 {
+            let val: u8 = rng.gen();
+            wire.set(&mut circuit, val);
+            circuit.run_until_done();
+            assert_eq!(low.read::<u8>(&circuit) >> 4, 0, "high nibble of {val:#x}");
+            assert_eq!(low.read::<u8>(&circuit), val & 0x0F);
+            assert_eq!(high.read::<u8>(&circuit), val | 0xF0);
+        }
+    }
+
+    #[test]
+    fn decode_with_enable_test() {
+        let mut circuit = Circuit::default();
+        let address = Wire::<3>::new(&mut circuit);
+        let enable = circuit.create_input();
+        let selects = address.decode_with_enable::<8>(&mut circuit, enable);
+
+        for val in 0..8u8 {
+            address.set(&mut circuit, val);
+            circuit.set_input(enable, false);
+            circuit.run_until_done();
+            assert_eq!(selects.read::<u8>(&circuit), 0, "{val} disabled");
+            circuit.set_input(enable, true);
+            circuit.run_until_done();
+            assert_eq!(selects.read::<u8>(&circuit), 1 << val, "{val} enabled");
+        }
+    }
+
+    #[test]
+    fn increment_decrement_test() {
+        let mut circuit = Circuit::default();
+        let wire = Wire::<16>::new(&mut circuit);
+        let (incremented, carry) = wire.increment(&mut circuit);
+        let (decremented, borrow) = wire.decrement(&mut circuit);
+
+        let mut rng = seeded_rng();
+        let vals = [0, 1, u16::MAX - 1, u16::MAX].into_iter();
+        for val in vals.chain((0..100).map(|_| rng.gen())) {
+            wire.set(&mut circuit, val);
+            circuit.run_until_done();
+            assert_eq!(incremented.read::<u16>(&circuit), val.wrapping_add(1));
+            assert_eq!(decremented.read::<u16>(&circuit), val.wrapping_sub(1));
+            assert_eq!(circuit.get_output(carry), val == u16::MAX, "{val}");
+            assert_eq!(circuit.get_output(borrow), val == 0, "{val}");
+        }
+    }
+
+    #[test]
+    fn fold_gate_test() {
+        let mut circuit = Circuit::default();
+        let wire = Wire::<4>::new(&mut circuit);
+        let all = wire.fold_gate(&mut circuit, NodeType::And);
+        let any = wire.reduce_or(&mut circuit);
+        assert_eq!(circuit.num_nodes(), 6);
+        for val in 0..16u8 {
+            wire.set(&mut circuit, val);
+            circuit.run_until_done();
+            assert_eq!(circuit.get_output(all), val == 0xF, "{val:#x}");
+            assert_eq!(circuit.get_output(any), val != 0, "{val:#x}");
+        }
     }
 }
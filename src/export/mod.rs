@@ -0,0 +1,2 @@
+pub mod netlist;
+pub mod verilog;
@@ -0,0 +1,49 @@
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{import::netlist::keyword, Circuit, NodeId};
+
+/// The circuit in the line based format read by
+/// [crate::import::netlist::from_netlist]. Nodes are named after the
+/// first of `inputs` or `outputs` they're in, or else `n<index>` by the
+/// order they were created. Nodes with nothing connected into them that
+/// aren't in `inputs` are written as gates without inputs.
+pub fn to_netlist(
+    circuit: &Circuit,
+    inputs: &[(NodeId, &str)],
+    outputs: &[(NodeId, &str)],
+) -> String {
+    let mut names: HashMap<NodeId, String> = HashMap::new();
+    for (node_id, name) in inputs.iter().chain(outputs.iter()) {
+        names.entry(*node_id).or_insert_with(|| name.to_string());
+    }
+    for (i, node_id) in circuit.node_ids().enumerate() {
+        names.entry(node_id).or_insert_with(|| format!("n{i}"));
+    }
+    let mut parents: HashMap<NodeId, Vec<&str>> = HashMap::new();
+    for (input, output) in circuit.edges() {
+        parents.entry(output).or_default().push(&names[&input]);
+    }
+
+    let mut netlist = String::new();
+    for node_id in circuit.node_ids() {
+        if inputs.iter().any(|(input, _)| *input == node_id) {
+            writeln!(netlist, "input {}", names[&node_id]).unwrap();
+            continue;
+        }
+        write!(
+            netlist,
+            "{} {}",
+            keyword(circuit.node_kind(node_id)),
+            names[&node_id]
+        )
+        .unwrap();
+        for parent in parents.remove(&node_id).unwrap_or_default() {
+            write!(netlist, " {parent}").unwrap();
+        }
+        netlist.push('\n');
+    }
+    for (node_id, _) in outputs {
+        writeln!(netlist, "output {}", names[node_id]).unwrap();
+    }
+    netlist
+}
@@ -0,0 +1,195 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use crate::{
+    circuit_sim::{CircuitSim, NodeType},
+    Circuit, NodeId,
+};
+
+/// A structural Verilog module for the circuit, for taking it into other
+/// EDA tools. Every node other than `inputs` gets a wire and an `assign`
+/// according to its gate, and `outputs` are assigned from the nodes they
+/// name. Nodes with nothing connected into them that aren't in `inputs`,
+/// like clocks, are tied to whatever they currently output. Wires are named
+/// after [Circuit::set_node_name] where it doesn't clash with another name.
+/// Everything is flattened into one module, so wires of nodes created in a
+/// [Circuit::begin_module] get a `hierarchy` attribute with the path of
+/// modules instead, like `(* hierarchy = "cpu.alu" *)`.
+///
+/// Names aren't checked, so they must be valid and distinct Verilog
+/// identifiers, and mustn't look like the generated `n<index>` wires.
+pub fn to_verilog(
+    circuit: &Circuit,
+    inputs: &[(NodeId, &str)],
+    outputs: &[(NodeId, &str)],
+) -> String {
+    let mut names: HashMap<NodeId, String> = inputs
+        .iter()
+        .map(|(node_id, name)| (*node_id, name.to_string()))
+        .collect();
+    // Numbered in the order the nodes were created
+    let gates: Vec<_> = circuit
+        .node_ids()
+        .enumerate()
+        .filter(|(_, node_id)| !names.contains_key(node_id))
+        .collect();
+    let mut taken: HashSet<_> = inputs
+        .iter()
+        .chain(outputs.iter())
+        .map(|(_, name)| name.to_string())
+        .collect();
+    for (i, node_id) in gates.iter().cloned() {
+        let name = match circuit.node_name(node_id) {
+            Some(name) if taken.insert(name.to_string()) => name.to_string(),
+            _ => format!("n{i}"),
+        };
+        names.insert(node_id, name);
+    }
+    let gates: Vec<_> = gates.into_iter().map(|(_, node_id)| node_id).collect();
+    let mut parents: HashMap<NodeId, Vec<&str>> = HashMap::new();
+    for (input, output) in circuit.edges() {
+        parents.entry(output).or_default().push(&names[&input]);
+    }
+
+    let mut verilog = String::from("module circuit (\n");
+    let ports: Vec<_> = inputs
+        .iter()
+        .map(|(_, name)| format!("    input wire {name}"))
+        .chain(
+            outputs
+                .iter()
+                .map(|(_, name)| format!("    output wire {name}")),
+        )
+        .collect();
+    verilog.push_str(&ports.join(",\n"));
+    verilog.push_str("\n);\n");
+
+    for node_id in gates.iter().cloned() {
+        let path = circuit.module_path(node_id);
+        if !path.is_empty() {
+            let path = path.join(".").replace('"', "\\\"");
+            writeln!(verilog, "    (* hierarchy = \"{path}\" *)").unwrap();
+        }
+        writeln!(verilog, "    wire {};", names[&node_id]).unwrap();
+    }
+    for node_id in gates.iter().cloned() {
+        let node_parents = parents.remove(&node_id).unwrap_or_default();
+        let expression = gate_expression(circuit, node_id, &node_parents);
+        writeln!(verilog, "    assign {} = {expression};", names[&node_id]).unwrap();
+    }
+    for (node_id, name) in outputs {
+        writeln!(verilog, "    assign {name} = {};", names[node_id]).unwrap();
+    }
+    verilog.push_str("endmodule\n");
+    verilog
+}
+
+/// The right hand side of the `assign` for a node fed by `parents`.
+fn gate_expression(circuit: &Circuit, node_id: NodeId, parents: &[&str]) -> String {
+    let (operator, inverted) = match circuit.node_kind(node_id) {
+        NodeType::Or | NodeType::Buffer => ('|', false),
+        NodeType::Nor | NodeType::Not => ('|', true),
+        NodeType::And => ('&', false),
+        NodeType::Nand => ('&', true),
+        NodeType::Xor => ('^', false),
+        NodeType::Xnor => ('^', true),
+        NodeType::Const(val) => return constant(val),
+    };
+    let not = if inverted { "~" } else { "" };
+    match parents {
+        [] => constant(circuit.get_output(node_id)),
+        [parent] => format!("{not}{parent}"),
+        [a, b] if inverted => format!("~({a} {operator} {b})"),
+        [a, b] => format!("{a} {operator} {b}"),
+        // Reduction operators take any number of inputs at once
+        _ => format!("{not}{operator}{{{}}}", parents.join(", ")),
+    }
+}
+
+fn constant(val: bool) -> String {
+    format!("1'b{}", val as u8)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, sync::Arc};
+
+    use crate::{
+        circuit_builder::{ops::*, CircuitBuilder, CircuitBuilderWithHooks, Connector, Names},
+        circuit_sim::CircuitSim,
+        components::adder::RippleCarryAdder,
+    };
+
+    use super::to_verilog;
+
+    #[test]
+    fn adder_verilog_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let (cin, _) = Connector::input(builder.clone());
+        let rca = RippleCarryAdder::<2>::new(builder.clone(), cin);
+        let mut borrow = builder.borrow_mut();
+        let (circuit, _) = borrow.build();
+
+        let input_names: Vec<_> = (0..2)
+            .flat_map(|i| [format!("a{i}"), format!("b{i}")])
+            .chain(["cin".to_string()])
+            .collect();
+        let input_ids = (0..2)
+            .flat_map(|i| [rca.input_a[i], rca.input_b[i]])
+            .chain([rca.cin]);
+        let inputs: Vec<_> = input_ids
+            .zip(input_names.iter().map(String::as_str))
+            .collect();
+        let outputs = [(rca.sum[0], "s0"), (rca.sum[1], "s1"), (rca.cout, "cout")];
+        let verilog = to_verilog(circuit, &inputs, &outputs);
+
+        assert!(verilog.starts_with("module circuit ("));
+        assert!(verilog.ends_with("endmodule\n"));
+        assert!(verilog.contains("    input wire cin"));
+        assert!(verilog.contains("    output wire cout\n"));
+        let gates = verilog
+            .lines()
+            .filter(|line| line.starts_with("    assign n"))
+            .count();
+        assert_eq!(gates, circuit.num_nodes() - inputs.len());
+        // The first sum bit is a three input XOR
+        let sum_wire = format!(
+            "n{}",
+            circuit
+                .node_ids()
+                .position(|node_id| node_id == rca.sum[0])
+                .unwrap()
+        );
+        assert!(verilog.contains(&format!("    assign {sum_wire} = ^{{cin, a0, b0}};")));
+        assert!(verilog.contains(&format!(
+            "    (* hierarchy = \"ripple_carry_adder\" *)\n    wire {sum_wire};\n"
+        )));
+        assert!(verilog.contains(&format!("    assign s0 = {sum_wire};")));
+    }
+
+    #[test]
+    fn named_wires_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilderWithHooks::<Names>::default()));
+        let (a, _) = Connector::input(builder.clone());
+        let (b, _) = Connector::input(builder.clone());
+        let carry = and!(a, b);
+        carry.mark("carry_out".to_string());
+        // Clashes with the output port, so keeps its generated name
+        let sum = xor!(a, b);
+        sum.mark("sum".to_string());
+        let mut borrow = builder.borrow_mut();
+        let circuit = borrow.build_with_names();
+        circuit.set_node_name(a.output, "ignored");
+
+        let inputs = [(a.output, "a"), (b.output, "b")];
+        let outputs = [(carry.output, "cout"), (sum.output, "sum")];
+        let verilog = to_verilog(circuit, &inputs, &outputs);
+        assert!(verilog.contains("    wire carry_out;\n"));
+        assert!(verilog.contains("    assign carry_out = a & b;\n"));
+        assert!(verilog.contains("    assign cout = carry_out;\n"));
+        assert!(verilog.contains("    assign sum = n3;\n"));
+        assert!(!verilog.contains("ignored"));
+    }
+}
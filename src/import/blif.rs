@@ -0,0 +1,355 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{self, Read},
+};
+
+use crate::{
+    circuit_sim::{CircuitSim, NodeType, RunResult, Ticks},
+    Circuit, NodeId,
+};
+
+#[derive(Debug)]
+pub enum BlifError {
+    Io(io::Error),
+    /// A line that couldn't be parsed, or uses a part of BLIF that isn't
+    /// supported, like latches or subcircuits.
+    Syntax {
+        line: usize,
+        message: String,
+    },
+    /// A signal is read or listed as an output, but nothing drives it.
+    Undriven(String),
+    /// A signal is driven by more than one input or cover.
+    Redefined(String),
+    /// The covers form a loop that never settles.
+    Unstable,
+}
+
+impl Display for BlifError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BlifError::Io(err) => write!(f, "Failed to read BLIF: {err}"),
+            BlifError::Syntax { line, message } => write!(f, "Line {line}: {message}"),
+            BlifError::Undriven(name) => write!(f, "Signal {name} is never driven"),
+            BlifError::Redefined(name) => write!(f, "Signal {name} is driven more than once"),
+            BlifError::Unstable => write!(f, "Circuit never settles, the covers form a loop"),
+        }
+    }
+}
+
+impl Error for BlifError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BlifError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BlifError {
+    fn from(err: io::Error) -> Self {
+        BlifError::Io(err)
+    }
+}
+
+/// A `.names` table: `output` is high when the inputs match any row whose
+/// output is `1`, or low when they match any row whose output is `0`.
+struct Cover {
+    inputs: Vec<String>,
+    output: String,
+    // Each row's input plane, one of `0`, `1` or `-` per input
+    rows: Vec<Vec<u8>>,
+    on_set: bool,
+}
+
+/// The most inputs a gate built for a cover gets, well below what a gate
+/// can count.
+const MAX_FAN_IN: usize = 16;
+
+/// Connects `inputs` into `output`, through a balanced tree of
+/// `node_type` gates if there are more than [MAX_FAN_IN] of them.
+/// `node_type` must be [NodeType::And] or [NodeType::Or], which can be
+/// split up this way, while `output` can be the inverted version.
+fn gate_tree(circuit: &mut Circuit, node_type: NodeType, mut inputs: Vec<NodeId>, output: NodeId) {
+    while inputs.len() > MAX_FAN_IN {
+        inputs = inputs
+            .chunks(MAX_FAN_IN)
+            .map(|chunk| {
+                let gate = circuit.create_node(node_type);
+                for input in chunk.iter().cloned() {
+                    circuit.connect(input, gate);
+                }
+                gate
+            })
+            .collect();
+    }
+    for input in inputs {
+        circuit.connect(input, output);
+    }
+}
+
+fn syntax<T>(line: usize, message: impl Into<String>) -> Result<T, BlifError> {
+    Err(BlifError::Syntax {
+        line,
+        message: message.into(),
+    })
+}
+
+/// The lines of the file with comments stripped and continuations joined,
+/// each with the number of the line it started on.
+fn logical_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut pending: Option<(usize, String)> = None;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let (line, continues) = match line.trim_end().strip_suffix('\\') {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        let (start, mut joined) = pending.take().unwrap_or((i + 1, String::new()));
+        joined.push(' ');
+        joined.push_str(line);
+        if continues {
+            pending = Some((start, joined));
+        } else if !joined.trim().is_empty() {
+            lines.push((start, joined));
+        }
+    }
+    lines.extend(pending);
+    lines
+}
+
+/// Builds a circuit from the first model in a BLIF netlist. Only
+/// combinational logic is supported: `.inputs`, `.outputs` and single
+/// output `.names` cover tables. Each row of a cover becomes an AND of its
+/// literals, and the rows are ORed together, or NORed for a cover of the
+/// off-set, splitting wide gates into trees. Returns the circuit along with
+/// the node of every named signal, where the inputs can be set and the
+/// outputs read, or [BlifError::Unstable] if the covers never settle.
+pub fn from_blif<R: Read>(mut reader: R) -> Result<(Circuit, HashMap<String, NodeId>), BlifError> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    let mut covers: Vec<Cover> = Vec::new();
+    let mut in_cover = false;
+    for (line, text) in logical_lines(&text) {
+        let words: Vec<_> = text.split_whitespace().collect();
+        if words[0].starts_with('.') {
+            in_cover = false;
+        }
+        match words[0] {
+            ".model" => {}
+            ".inputs" => inputs.extend(words[1..].iter().map(|word| word.to_string())),
+            ".outputs" => outputs.extend(words[1..].iter().map(|word| word.to_string())),
+            ".names" => {
+                let Some((output, inputs)) = words[1..].split_last() else {
+                    return syntax(line, ".names needs an output");
+                };
+                covers.push(Cover {
+                    inputs: inputs.iter().map(|word| word.to_string()).collect(),
+                    output: output.to_string(),
+                    rows: Vec::new(),
+                    on_set: true,
+                });
+                in_cover = true;
+            }
+            ".end" => break,
+            directive if directive.starts_with('.') => {
+                return syntax(line, format!("Unsupported directive {directive}"));
+            }
+            _ if !in_cover => return syntax(line, "Cover row outside of .names"),
+            _ => {
+                let cover = covers.last_mut().unwrap();
+                let (plane, output) = match words[..] {
+                    [output] if cover.inputs.is_empty() => ("", output),
+                    [plane, output] => (plane, output),
+                    _ => return syntax(line, "Cover rows need an input plane and an output"),
+                };
+                if plane.len() != cover.inputs.len()
+                    || !plane.bytes().all(|literal| b"01-".contains(&literal))
+                {
+                    return syntax(line, format!("Bad input plane {plane}"));
+                }
+                let on_set = match output {
+                    "1" => true,
+                    "0" => false,
+                    _ => return syntax(line, format!("Bad output {output}")),
+                };
+                if !cover.rows.is_empty() && on_set != cover.on_set {
+                    return syntax(line, "Cover mixes on-set and off-set rows");
+                }
+                cover.on_set = on_set;
+                cover.rows.push(plane.bytes().collect());
+            }
+        }
+    }
+
+    let mut circuit = Circuit::new();
+    let mut signals = HashMap::new();
+    for name in inputs {
+        let input = circuit.create_input();
+        if signals.insert(name.clone(), input).is_some() {
+            return Err(BlifError::Redefined(name));
+        }
+    }
+    // Covers can read signals driven by covers further down, so every
+    // output is created before any rows
+    for cover in covers.iter() {
+        let node_type = if cover.on_set {
+            NodeType::Or
+        } else {
+            NodeType::Nor
+        };
+        let output = circuit.create_node(node_type);
+        if signals.insert(cover.output.clone(), output).is_some() {
+            return Err(BlifError::Redefined(cover.output.clone()));
+        }
+    }
+
+    let mut inverted = HashMap::new();
+    for cover in covers.iter() {
+        let output = signals[&cover.output];
+        let cover_inputs = cover
+            .inputs
+            .iter()
+            .map(|name| {
+                signals
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| BlifError::Undriven(name.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut terms = Vec::with_capacity(cover.rows.len());
+        for row in cover.rows.iter() {
+            let mut literals = Vec::new();
+            for (literal, input) in row.iter().zip(cover_inputs.iter().cloned()) {
+                match literal {
+                    b'1' => literals.push(input),
+                    b'0' => literals.push(*inverted.entry(input).or_insert_with(|| {
+                        let input_not = circuit.create_node(NodeType::Not);
+                        circuit.connect(input, input_not);
+                        input_not
+                    })),
+                    _ => {}
+                }
+            }
+            let term = circuit.create_node(NodeType::And);
+            gate_tree(&mut circuit, NodeType::And, literals, term);
+            terms.push(term);
+        }
+        gate_tree(&mut circuit, NodeType::Or, terms, output);
+    }
+
+    if let Some(output) = outputs.iter().find(|output| !signals.contains_key(*output)) {
+        return Err(BlifError::Undriven(output.clone()));
+    }
+    // Without loops, settling takes at most a tick per node
+    let max_ticks = circuit.num_nodes() as Ticks + 1;
+    if let RunResult::ReachedMaxTicks { .. } = circuit.run(max_ticks) {
+        return Err(BlifError::Unstable);
+    }
+    Ok((circuit, signals))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::circuit_sim::CircuitSim;
+
+    use super::{from_blif, BlifError};
+
+    #[test]
+    fn xor_test() {
+        let blif = "\
+# Two input XOR
+.model xor
+.inputs a \\
+    b
+.outputs y
+.names a b y
+01 1
+10 1
+.end
+";
+        let (mut circuit, signals) = from_blif(blif.as_bytes()).unwrap();
+        let (a, b, y) = (signals["a"], signals["b"], signals["y"]);
+        for (val_a, val_b) in [(false, false), (false, true), (true, false), (true, true)] {
+            let outputs = circuit.apply(&[(a, val_a), (b, val_b)], &[y]);
+            assert_eq!(outputs, [val_a ^ val_b], "{val_a} ^ {val_b}");
+        }
+    }
+
+    #[test]
+    fn off_set_and_constant_test() {
+        // y = !(a & b), built from its off-set and read through a later
+        // cover, with constant one and zero covers
+        let blif = "\
+.model nand
+.inputs a b
+.outputs z one zero
+.names y z
+1 1
+.names a b y
+11 0
+.names one
+1
+.names zero
+";
+        let (mut circuit, signals) = from_blif(blif.as_bytes()).unwrap();
+        let (a, b) = (signals["a"], signals["b"]);
+        let outputs = [signals["z"], signals["one"], signals["zero"]];
+        for (val_a, val_b) in [(false, false), (false, true), (true, false), (true, true)] {
+            assert_eq!(
+                circuit.apply(&[(a, val_a), (b, val_b)], &outputs),
+                [!(val_a && val_b), true, false]
+            );
+        }
+
+        let undriven = ".model bad\n.outputs y\n.names x y\n1 1\n";
+        assert!(matches!(
+            from_blif(undriven.as_bytes()),
+            Err(BlifError::Undriven(name)) if name == "x"
+        ));
+        let latch = ".model bad\n.inputs d\n.latch d q re clk 0\n";
+        assert!(matches!(
+            from_blif(latch.as_bytes()),
+            Err(BlifError::Syntax { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn wide_cover_test() {
+        // More rows and literals than a gate can count
+        let mut blif = String::from(".model wide\n.inputs a\n.outputs y all\n.names a y\n");
+        blif.push_str(&"1 1\n".repeat(256));
+        let names: Vec<_> = (0..300).map(|i| format!("b{i}")).collect();
+        blif.push_str(&format!(".inputs {}\n", names.join(" ")));
+        blif.push_str(&format!(
+            ".names {} all\n{} 1\n",
+            names.join(" "),
+            "1".repeat(300)
+        ));
+
+        let (mut circuit, signals) = from_blif(blif.as_bytes()).unwrap();
+        let (y, all) = (signals["y"], signals["all"]);
+        for val in [true, false, true] {
+            assert_eq!(circuit.apply(&[(signals["a"], val)], &[y]), [val]);
+        }
+        let inputs: Vec<_> = names.iter().map(|name| (signals[name], true)).collect();
+        assert_eq!(circuit.apply(&inputs, &[all]), [true]);
+        assert_eq!(circuit.apply(&[(signals["b123"], false)], &[all]), [false]);
+    }
+
+    #[test]
+    fn unstable_test() {
+        // z = !y and y = z, a ring that never settles
+        let blif = ".model ring\n.outputs y\n.names y z\n0 1\n.names z y\n1 1\n";
+        assert!(matches!(
+            from_blif(blif.as_bytes()),
+            Err(BlifError::Unstable)
+        ));
+    }
+}
@@ -0,0 +1,3 @@
+pub mod blif;
+pub mod netlist;
+pub mod stimulus;
@@ -0,0 +1,243 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use crate::{
+    circuit_sim::{CircuitSim, NodeType, RunResult, Ticks},
+    Circuit, NodeId,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetlistError {
+    /// A line that couldn't be parsed.
+    Syntax { line: usize, message: String },
+    /// A name is connected from or listed as an output, but never defined.
+    Undefined(String),
+    /// A name is defined more than once.
+    Redefined(String),
+    /// A gate lists itself as one of its inputs.
+    SelfInput(String),
+    /// The circuit never settles, likely because of an oscillating loop.
+    Unstable,
+}
+
+impl Display for NetlistError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            NetlistError::Syntax { line, message } => write!(f, "Line {line}: {message}"),
+            NetlistError::Undefined(name) => write!(f, "{name} is never defined"),
+            NetlistError::Redefined(name) => write!(f, "{name} is defined more than once"),
+            NetlistError::SelfInput(name) => write!(f, "{name} is connected to itself"),
+            NetlistError::Unstable => write!(f, "Circuit never settles"),
+        }
+    }
+}
+
+impl Error for NetlistError {}
+
+/// The keyword for each kind of node in a netlist.
+pub(crate) fn keyword(node_type: NodeType) -> &'static str {
+    match node_type {
+        NodeType::Or => "or",
+        NodeType::Nor => "nor",
+        NodeType::And => "and",
+        NodeType::Nand => "nand",
+        NodeType::Xor => "xor",
+        NodeType::Xnor => "xnor",
+        NodeType::Buffer => "buffer",
+        NodeType::Not => "not",
+        NodeType::Const(false) => "const0",
+        NodeType::Const(true) => "const1",
+    }
+}
+
+fn node_type(keyword: &str) -> Option<NodeType> {
+    let node_type = match keyword {
+        "or" => NodeType::Or,
+        "nor" => NodeType::Nor,
+        "and" => NodeType::And,
+        "nand" => NodeType::Nand,
+        "xor" => NodeType::Xor,
+        "xnor" => NodeType::Xnor,
+        "buffer" => NodeType::Buffer,
+        "not" => NodeType::Not,
+        "const0" => NodeType::Const(false),
+        "const1" => NodeType::Const(true),
+        _ => return None,
+    };
+    Some(node_type)
+}
+
+/// Builds a circuit from a line based netlist, for quickly sketching
+/// circuits by hand. Each line is one of:
+///
+/// ```text
+/// input <name>
+/// output <name>
+/// <gate> <name> <input name>...
+/// ```
+///
+/// where `<gate>` is the lowercase name of a [NodeType], or `const0` and
+/// `const1`. Gates can be connected from names defined further down, and
+/// `#` starts a comment. Returns the circuit settled, along with the node
+/// of every name, or [NetlistError::Unstable] if it never settles. [crate::export::netlist::to_netlist] writes the format
+/// back out.
+pub fn from_netlist(netlist: &str) -> Result<(Circuit, HashMap<String, NodeId>), NetlistError> {
+    let mut nodes = Vec::new();
+    let mut outputs = Vec::new();
+    for (i, line) in netlist.lines().enumerate() {
+        let line_number = i + 1;
+        let words: Vec<_> = line
+            .split('#')
+            .next()
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect();
+        match words[..] {
+            [] => {}
+            ["input", name] => nodes.push((None, name, Vec::new())),
+            ["output", name] => outputs.push(name),
+            ["input" | "output", ..] => {
+                return Err(NetlistError::Syntax {
+                    line: line_number,
+                    message: format!("{} takes a single name", words[0]),
+                })
+            }
+            [gate, name, ref inputs @ ..] => {
+                let Some(node_type) = node_type(gate) else {
+                    return Err(NetlistError::Syntax {
+                        line: line_number,
+                        message: format!("Unknown gate {gate}"),
+                    });
+                };
+                if matches!(node_type, NodeType::Const(_)) && !inputs.is_empty() {
+                    return Err(NetlistError::Syntax {
+                        line: line_number,
+                        message: "Constants can't have inputs".to_string(),
+                    });
+                }
+                nodes.push((Some(node_type), name, inputs.to_vec()));
+            }
+            [word] => {
+                return Err(NetlistError::Syntax {
+                    line: line_number,
+                    message: format!("{word} needs a name"),
+                })
+            }
+        }
+    }
+
+    let mut circuit = Circuit::new();
+    let mut names = HashMap::new();
+    for (node_type, name, _) in nodes.iter() {
+        let node_id = match node_type {
+            Some(node_type) => circuit.create_node(*node_type),
+            None => circuit.create_input(),
+        };
+        if names.insert(name.to_string(), node_id).is_some() {
+            return Err(NetlistError::Redefined(name.to_string()));
+        }
+    }
+    let lookup = |name: &str| {
+        names
+            .get(name)
+            .cloned()
+            .ok_or_else(|| NetlistError::Undefined(name.to_string()))
+    };
+    for (_, name, inputs) in nodes.iter() {
+        let output = names[*name];
+        for input in inputs.iter() {
+            if input == name {
+                return Err(NetlistError::SelfInput(name.to_string()));
+            }
+            circuit.connect(lookup(input)?, output);
+        }
+    }
+    for output in outputs {
+        lookup(output)?;
+    }
+    // Without loops, settling takes at most a tick per node
+    let max_ticks = circuit.num_nodes() as Ticks + 1;
+    if let RunResult::ReachedMaxTicks { .. } = circuit.run(max_ticks) {
+        return Err(NetlistError::Unstable);
+    }
+    Ok((circuit, names))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{circuit_sim::CircuitSim, export::netlist::to_netlist};
+
+    use super::{from_netlist, NetlistError};
+
+    const FULL_ADDER: &str = "\
+# Full adder
+input a
+input b
+input cin
+xor sum half cin
+xor half a b
+and carry half cin
+and half_carry a b
+or cout carry half_carry
+output sum
+output cout
+";
+
+    #[test]
+    fn round_trip_test() {
+        let (circuit, names) = from_netlist(FULL_ADDER).unwrap();
+        let inputs = ["a", "b", "cin"].map(|name| (names[name], name));
+        let outputs = ["sum", "cout"].map(|name| (names[name], name));
+        let netlist = to_netlist(&circuit, &inputs, &outputs);
+
+        let (mut copy, copy_names) = from_netlist(&netlist).unwrap();
+        let copy_inputs = ["a", "b", "cin"].map(|name| (copy_names[name], name));
+        let copy_outputs = ["sum", "cout"].map(|name| (copy_names[name], name));
+        assert_eq!(to_netlist(&copy, &copy_inputs, &copy_outputs), netlist);
+
+        for val in 0..8u32 {
+            let bits = [0, 1, 2].map(|bit| val & (1 << bit) != 0);
+            let set: Vec<_> = copy_inputs
+                .iter()
+                .zip(bits)
+                .map(|((input, _), bit)| (*input, bit))
+                .collect();
+            let outputs: Vec<_> = copy_outputs.iter().map(|(output, _)| *output).collect();
+            let sum = val.count_ones();
+            assert_eq!(
+                copy.apply(&set, &outputs),
+                [sum & 1 != 0, sum & 2 != 0],
+                "inputs {val:#b}"
+            );
+        }
+    }
+
+    #[test]
+    fn errors_test() {
+        assert_eq!(
+            from_netlist("input a\nnand b a c\n").unwrap_err(),
+            NetlistError::Undefined("c".to_string())
+        );
+        assert_eq!(
+            from_netlist("input a\nnot a a\n").unwrap_err(),
+            NetlistError::Redefined("a".to_string())
+        );
+        assert!(matches!(
+            from_netlist("input a\n\nlatch b a\n").unwrap_err(),
+            NetlistError::Syntax { line: 3, .. }
+        ));
+        assert_eq!(
+            from_netlist("input a\nor q q a\n").unwrap_err(),
+            NetlistError::SelfInput("q".to_string())
+        );
+        assert_eq!(
+            from_netlist("not b d\nnot c b\nnot d c\n").unwrap_err(),
+            NetlistError::Unstable
+        );
+        // A latch settles even though it loops
+        assert!(from_netlist("input s\ninput r\nnor q r qn\nnor qn s q\n").is_ok());
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{self, Read},
+};
+
+use crate::{circuit_sim::CircuitSim, Circuit, InputId, NodeId};
+
+#[derive(Debug)]
+pub enum StimulusError {
+    Io(io::Error),
+    /// A row with the wrong number of values, or a value that isn't `0` or
+    /// `1`.
+    Syntax {
+        line: usize,
+        message: String,
+    },
+    /// An input is mapped to a column the header doesn't have.
+    MissingColumn(String),
+}
+
+impl Display for StimulusError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StimulusError::Io(err) => write!(f, "Failed to read stimulus: {err}"),
+            StimulusError::Syntax { line, message } => write!(f, "Line {line}: {message}"),
+            StimulusError::MissingColumn(name) => write!(f, "No column named {name}"),
+        }
+    }
+}
+
+impl Error for StimulusError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StimulusError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StimulusError {
+    fn from(err: io::Error) -> Self {
+        StimulusError::Io(err)
+    }
+}
+
+fn split_row(line: &str) -> Vec<&str> {
+    line.split(',').map(str::trim).collect()
+}
+
+impl Circuit {
+    /// Replays recorded test vectors from a CSV whose header names the
+    /// columns, one row per cycle. For each row the inputs in `input_map`
+    /// are set from their column, either `0` or `1`, and the circuit
+    /// settles, then `clock` is pulsed high and low if given, settling
+    /// after each. Returns the `outputs` read at the end of every row.
+    /// Columns nothing is mapped to are ignored, so expected outputs can
+    /// live in the same file. Rows before a bad one are still applied.
+    pub fn apply_stimulus_csv(
+        &mut self,
+        mut r: impl Read,
+        input_map: &[(InputId, &str)],
+        outputs: &[NodeId],
+        clock: Option<InputId>,
+    ) -> Result<Vec<Vec<bool>>, StimulusError> {
+        let mut text = String::new();
+        r.read_to_string(&mut text)?;
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line))
+            .filter(|(_, line)| !line.trim().is_empty());
+        let Some((_, header)) = lines.next() else {
+            return Ok(Vec::new());
+        };
+        let header = split_row(header);
+        let columns = input_map
+            .iter()
+            .map(
+                |(input, name)| match header.iter().position(|column| column == name) {
+                    Some(column) => Ok((*input, column)),
+                    None => Err(StimulusError::MissingColumn(name.to_string())),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut results = Vec::new();
+        for (line, row) in lines {
+            let values = split_row(row);
+            if values.len() != header.len() {
+                return Err(StimulusError::Syntax {
+                    line,
+                    message: format!("Expected {} values, found {}", header.len(), values.len()),
+                });
+            }
+            for (input, column) in columns.iter().cloned() {
+                let val = match values[column] {
+                    "0" => false,
+                    "1" => true,
+                    value => {
+                        return Err(StimulusError::Syntax {
+                            line,
+                            message: format!(
+                                "Expected 0 or 1 for {}, found {value}",
+                                header[column]
+                            ),
+                        })
+                    }
+                };
+                self.set_input(input, val);
+            }
+            self.run_until_done();
+            if let Some(clock) = clock {
+                self.set_input(clock, true);
+                self.run_until_done();
+                self.set_input(clock, false);
+                self.run_until_done();
+            }
+            results.push(
+                outputs
+                    .iter()
+                    .map(|output| self.get_output(*output))
+                    .collect(),
+            );
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{circuit_sim::CircuitSim, components::register::Register, Circuit};
+
+    use super::StimulusError;
+
+    #[test]
+    fn stimulus_test() {
+        let mut circuit = Circuit::new();
+        let register = Register::<2>::new(&mut circuit);
+        let input_map = [
+            (register.input[0], "d0"),
+            (register.input[1], "d1"),
+            (register.enable, "en"),
+        ];
+        let outputs = [register.output[0], register.output[1]];
+        let csv = "d0, d1, en, expected\n\
+                   1, 0, 1, 01\n\
+                   \n\
+                   0, 1, 0, 01\n\
+                   0, 1, 1, 10\n\
+                   1, 1, 1, 11\n";
+        let results = circuit
+            .apply_stimulus_csv(csv.as_bytes(), &input_map, &outputs, Some(register.clk))
+            .unwrap();
+        let expected = [[true, false], [true, false], [false, true], [true, true]];
+        assert_eq!(results, expected);
+
+        let missing = circuit.apply_stimulus_csv("d0\n1\n".as_bytes(), &input_map, &outputs, None);
+        assert!(matches!(missing, Err(StimulusError::MissingColumn(name)) if name == "d1"));
+        let bad_value = circuit.apply_stimulus_csv(
+            "d0,d1,en\n0,0,0\n1,x,0\n".as_bytes(),
+            &input_map,
+            &outputs,
+            None,
+        );
+        assert!(matches!(
+            bad_value,
+            Err(StimulusError::Syntax { line: 3, .. })
+        ));
+    }
+}
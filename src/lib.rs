@@ -1,8 +1,23 @@
+// The builder API is built around [Arc<RefCell<_>>]
+#![allow(clippy::arc_with_non_send_sync)]
+
 pub mod circuit_builder;
+pub mod circuit_fragment;
 pub mod circuit_sim;
+pub mod circuit_thread;
 pub mod components;
+pub mod export;
+pub mod import;
+pub mod vcd;
+pub mod verify;
+
+#[cfg(test)]
+mod test_utils;
 
 mod circuit;
-pub use circuit::Circuit;
+pub use circuit::{
+    Circuit, CircuitDescriptor, CircuitSnapshot, DescriptorError, ExtractError, Extracted,
+    LoopKind, OutOfNodeIds, Probe,
+};
 pub type NodeId = <Circuit as circuit_sim::CircuitSim>::NodeId;
 pub type InputId = <Circuit as circuit_sim::CircuitSim>::InputId;
@@ -0,0 +1,13 @@
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+/// Rng for randomized tests. The seed is printed, which the test harness
+/// shows on failure, and can be overridden with `DIGISIM_SEED` to reproduce
+/// a failing run.
+pub fn seeded_rng() -> StdRng {
+    let seed = match std::env::var("DIGISIM_SEED") {
+        Ok(seed) => seed.parse().expect("DIGISIM_SEED should be a u64"),
+        Err(_) => rand::thread_rng().next_u64(),
+    };
+    println!("DIGISIM_SEED={seed}");
+    StdRng::seed_from_u64(seed)
+}
@@ -0,0 +1,207 @@
+use std::io::{self, Write};
+
+use crate::{circuit_sim::CircuitSim, Circuit, NodeId};
+
+/// Writes the outputs of a set of nodes as a VCD waveform, which can be
+/// viewed in tools like GTKWave. Each tick of the simulation is one unit of
+/// time.
+pub struct VcdWriter<W: Write> {
+    writer: W,
+    signals: Vec<(NodeId, String)>,
+    // What each signal was at the last sample, or [None] before the first
+    values: Option<Vec<bool>>,
+    // How many of the circuit's phases have been written
+    phases_written: usize,
+}
+
+impl<W: Write> VcdWriter<W> {
+    /// Writes the header declaring one wire per signal, named by its
+    /// string. Whitespace isn't allowed in names, so it's replaced.
+    pub fn new(mut writer: W, signals: &[(NodeId, String)]) -> io::Result<Self> {
+        writeln!(writer, "$timescale 1ns $end")?;
+        writeln!(writer, "$scope module circuit $end")?;
+        let mut identified = Vec::with_capacity(signals.len());
+        for (i, (node_id, name)) in signals.iter().enumerate() {
+            let identifier = Self::identifier(i);
+            let name = name.replace(char::is_whitespace, "_");
+            writeln!(writer, "$var wire 1 {identifier} {name} $end")?;
+            identified.push((*node_id, identifier));
+        }
+        writeln!(writer, "$upscope $end")?;
+        writeln!(writer, "$enddefinitions $end")?;
+        Ok(VcdWriter {
+            writer,
+            signals: identified,
+            values: None,
+            phases_written: 0,
+        })
+    }
+
+    /// Short identifier for the `index`th signal, out of the printable ASCII
+    /// characters VCD allows.
+    fn identifier(mut index: usize) -> String {
+        const FIRST: u8 = b'!';
+        const COUNT: usize = (b'~' - FIRST + 1) as usize;
+        let mut identifier = String::new();
+        loop {
+            identifier.push((FIRST + (index % COUNT) as u8) as char);
+            index /= COUNT;
+            if index == 0 {
+                return identifier;
+            }
+            index -= 1;
+        }
+    }
+
+    /// Records the signals at the circuit's current tick. Only signals that
+    /// changed since the last sample are written, and nothing at all if none
+    /// did. Meant to be called once per tick, so ticks mustn't go backwards.
+    ///
+    /// Phases marked with [Circuit::mark_phase] since the last sample are
+    /// written as comments at this sample's time.
+    pub fn sample(&mut self, circuit: &Circuit) -> io::Result<()> {
+        let values: Vec<_> = self
+            .signals
+            .iter()
+            .map(|(node_id, _)| circuit.get_output(*node_id))
+            .collect();
+        let changed: Vec<_> = match &self.values {
+            None => (0..values.len()).collect(),
+            Some(last) => (0..values.len())
+                .filter(|i| last[*i] != values[*i])
+                .collect(),
+        };
+        let phases = circuit.phases().get(self.phases_written..).unwrap_or(&[]);
+        if !changed.is_empty() || !phases.is_empty() {
+            writeln!(self.writer, "#{}", circuit.tick())?;
+            for (_, name) in phases {
+                writeln!(self.writer, "$comment phase {name} $end")?;
+            }
+            self.phases_written += phases.len();
+            for i in changed {
+                writeln!(self.writer, "{}{}", values[i] as u8, self.signals[i].1)?;
+            }
+        }
+        self.values = Some(values);
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{circuit_sim::CircuitSim, components::flipflop::RippleCounter, Circuit};
+
+    use super::VcdWriter;
+
+    #[test]
+    fn identifier_test() {
+        let identifiers: Vec<_> = (0..10_000).map(VcdWriter::<Vec<u8>>::identifier).collect();
+        assert_eq!(identifiers[0], "!");
+        assert_eq!(identifiers[93], "~");
+        assert_eq!(identifiers[94], "!!");
+        let mut unique = identifiers.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), identifiers.len());
+    }
+
+    #[test]
+    fn phase_test() {
+        let mut circuit = Circuit::new();
+        let clock = circuit.clock(4);
+        let signals = [(clock, "clock".to_string())];
+        let mut vcd = VcdWriter::new(Vec::new(), &signals).unwrap();
+        circuit.mark_phase("fetch");
+        vcd.sample(&circuit).unwrap();
+        for tick in 1..=10 {
+            circuit.update();
+            match tick {
+                3 => circuit.mark_phase("decode"),
+                6 => circuit.mark_phase("execute"),
+                _ => {}
+            }
+            vcd.sample(&circuit).unwrap();
+        }
+        let dump = String::from_utf8(vcd.into_inner()).unwrap();
+
+        let mut tick = None;
+        let mut phases = Vec::new();
+        for line in dump.lines() {
+            if let Some(time) = line.strip_prefix('#') {
+                tick = Some(time.parse::<u64>().unwrap());
+            } else if let Some(comment) = line.strip_prefix("$comment phase ") {
+                let name = comment.strip_suffix(" $end").unwrap();
+                phases.push((tick.unwrap(), name));
+            }
+        }
+        assert_eq!(
+            phases,
+            [(0, "fetch"), (3, "decode"), (6, "execute")],
+            "{dump}"
+        );
+    }
+
+    #[test]
+    fn counter_dump_test() {
+        let mut circuit = Circuit::new();
+        let counter = RippleCounter::<3>::new(&mut circuit);
+        circuit.run_until_done();
+        let signals: Vec<_> = counter
+            .bits
+            .iter()
+            .enumerate()
+            .map(|(i, bit)| (*bit, format!("count {i}")))
+            .collect();
+
+        let mut vcd = VcdWriter::new(Vec::new(), &signals).unwrap();
+        vcd.sample(&circuit).unwrap();
+        // The tick each count settled on, with the count
+        let mut settled = Vec::new();
+        for _ in 0..10 {
+            for clk in [true, false] {
+                circuit.set_input(counter.clk, clk);
+                while circuit.work_left() {
+                    circuit.update();
+                    vcd.sample(&circuit).unwrap();
+                }
+            }
+            settled.push((circuit.tick(), counter.read::<u64>(&circuit)));
+        }
+        let dump = String::from_utf8(vcd.into_inner()).unwrap();
+
+        // Count after each timestamp's changes
+        let mut identifiers = HashMap::new();
+        let mut counts: Vec<(u64, u64)> = Vec::new();
+        for line in dump.lines() {
+            let words: Vec<_> = line.split_whitespace().collect();
+            if let ["$var", "wire", "1", identifier, name, "$end"] = words[..] {
+                let bit: u32 = name.strip_prefix("count_").unwrap().parse().unwrap();
+                identifiers.insert(identifier.to_string(), bit);
+            } else if let Some(tick) = line.strip_prefix('#') {
+                let count = counts.last().map_or(0, |(_, count)| *count);
+                counts.push((tick.parse().unwrap(), count));
+            } else if let Some(identifier) = line.strip_prefix(['0', '1']) {
+                let bit = identifiers[identifier];
+                let (_, count) = counts.last_mut().unwrap();
+                *count = (*count & !(1 << bit)) | ((line.starts_with('1') as u64) << bit);
+            }
+        }
+        assert_eq!(identifiers.len(), 3);
+
+        // The carry ripples through the bits, so only the settled counts
+        // have to match
+        let expected: Vec<_> = (1..=10).map(|count| count % 8).collect();
+        let settled_counts: Vec<_> = settled.iter().map(|(_, count)| *count).collect();
+        assert_eq!(settled_counts, expected);
+        for (tick, count) in settled {
+            let (_, dumped) = counts.iter().rev().find(|(time, _)| *time <= tick).unwrap();
+            assert_eq!(*dumped, count, "tick {tick} of {dump}");
+        }
+    }
+}
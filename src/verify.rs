@@ -0,0 +1,899 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt::{self, Debug, Display, Formatter},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    circuit_sim::{CircuitSim, Tick},
+    Circuit, InputId, NodeId,
+};
+
+/// One clock cycle of a trace checked by [Circuit::assert_trace].
+#[derive(Clone, Copy, Debug)]
+pub struct TraceStep<'a> {
+    /// Applied while the clock is low, before the rising edge.
+    pub inputs: &'a [(InputId, bool)],
+    /// Expected once the circuit settles after the rising edge.
+    pub outputs: &'a [(NodeId, bool)],
+}
+
+/// Returned by [Circuit::output_function] when given more inputs than fit
+/// a truth table in a [u64], and by [Circuit::equivalent] when given more
+/// inputs than it was allowed to enumerate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TooManyInputs(pub usize);
+
+impl Display for TooManyInputs {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Truth table of {} inputs is too large", self.0)
+    }
+}
+
+impl Error for TooManyInputs {}
+
+/// Circuits with up to this many inputs are searched exhaustively by
+/// [Circuit::find_counterexample].
+const EXHAUSTIVE_MAX_INPUTS: usize = 16;
+
+/// Most inputs [Circuit::truth_table] will enumerate, which is already
+/// about a million rows.
+pub const TRUTH_TABLE_MAX_INPUTS: usize = 20;
+
+/// Number of random assignments [Circuit::find_counterexample] tries on
+/// circuits with more inputs than can be searched exhaustively.
+const RANDOM_VECTORS: usize = 1 << 12;
+
+impl Circuit {
+    /// Searches for values of `inputs` for which the `outputs` of the two
+    /// combinational circuits differ. Like [Self::lockstep], nodes are
+    /// matched by [NodeId]. With few enough inputs every assignment is
+    /// tried in order of how many inputs are set, so the counterexample
+    /// found is one with the fewest set inputs. Otherwise random
+    /// assignments are tried, so [None] only means none were found.
+    pub fn find_counterexample(
+        &mut self,
+        other: &mut Self,
+        inputs: &[InputId],
+        outputs: &[NodeId],
+    ) -> Option<Vec<bool>> {
+        let mut differs = |vals: &[bool]| {
+            let inputs: Vec<_> = inputs.iter().cloned().zip(vals.iter().cloned()).collect();
+            self.apply(&inputs, outputs) != other.apply(&inputs, outputs)
+        };
+
+        if inputs.len() <= EXHAUSTIVE_MAX_INPUTS {
+            let all = (1u32 << inputs.len()) - 1;
+            for set_inputs in 0..=inputs.len() {
+                // Gosper's hack, going through every mask with this many bits
+                let mut mask = (1u32 << set_inputs) - 1;
+                while mask <= all {
+                    let vals: Vec<_> = (0..inputs.len()).map(|i| mask & (1 << i) != 0).collect();
+                    if differs(&vals) {
+                        return Some(vals);
+                    }
+                    if mask == 0 {
+                        break;
+                    }
+                    let lowest = mask & mask.wrapping_neg();
+                    let ripple = mask + lowest;
+                    mask = (((ripple ^ mask) >> 2) / lowest) | ripple;
+                }
+            }
+            None
+        } else {
+            let mut rng = StdRng::seed_from_u64(0);
+            (0..RANDOM_VECTORS)
+                .map(|_| (0..inputs.len()).map(|_| rng.gen()).collect::<Vec<_>>())
+                .find(|vals| differs(vals))
+        }
+    }
+
+    /// The truth table of `output` over up to 6 `inputs`. Bit `i` of the
+    /// result is the output when each input `j` is set to bit `j` of `i`, so
+    /// a 2 input XOR gives `0b0110`. Inputs are restored afterwards.
+    pub fn output_function(
+        &mut self,
+        output: NodeId,
+        inputs: &[InputId],
+    ) -> Result<u64, TooManyInputs> {
+        if inputs.len() > 6 {
+            return Err(TooManyInputs(inputs.len()));
+        }
+        let original: Vec<_> = inputs
+            .iter()
+            .map(|input| (*input, self.get_output(*input)))
+            .collect();
+
+        let mut table = 0;
+        for row in 0..1u64 << inputs.len() {
+            let vals: Vec<_> = inputs
+                .iter()
+                .enumerate()
+                .map(|(i, input)| (*input, row & (1 << i) != 0))
+                .collect();
+            if self.apply(&vals, &[output])[0] {
+                table |= 1 << row;
+            }
+        }
+
+        self.apply(&original, &[]);
+        Ok(table)
+    }
+
+    /// Every combination of `inputs` along with the `outputs` it settles to,
+    /// packed so bit `i` of each is `inputs[i]` or `outputs[i]`, in order
+    /// of the packed inputs. Inputs are restored afterwards. Panics with
+    /// more than [TRUTH_TABLE_MAX_INPUTS] inputs or 64 outputs.
+    pub fn truth_table(&mut self, inputs: &[InputId], outputs: &[NodeId]) -> Vec<(u64, u64)> {
+        assert!(
+            inputs.len() <= TRUTH_TABLE_MAX_INPUTS,
+            "Truth table of {} inputs is too large, at most {TRUTH_TABLE_MAX_INPUTS} are supported",
+            inputs.len()
+        );
+        assert!(outputs.len() <= 64, "At most 64 outputs can be packed");
+        let original: Vec<_> = inputs
+            .iter()
+            .map(|input| (*input, self.get_output(*input)))
+            .collect();
+
+        let table = (0..1u64 << inputs.len())
+            .map(|row| {
+                let vals: Vec<_> = inputs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, input)| (*input, row & (1 << i) != 0))
+                    .collect();
+                let packed = self
+                    .apply(&vals, outputs)
+                    .into_iter()
+                    .enumerate()
+                    .fold(0, |packed, (i, val)| packed | (val as u64) << i);
+                (row, packed)
+            })
+            .collect();
+
+        self.apply(&original, &[]);
+        table
+    }
+
+    /// Checks that the `outputs` of this combinational circuit match the
+    /// `other_outputs` of another for every combination of inputs, with
+    /// `inputs` lined up with `other_inputs`. Unlike
+    /// [Self::find_counterexample] the circuits can have different
+    /// [NodeId]s. Returns the first combination that differs, packed like in
+    /// [Self::truth_table], or fails without trying any if there are more
+    /// than `max_inputs` inputs.
+    pub fn equivalent(
+        &mut self,
+        inputs: &[InputId],
+        outputs: &[NodeId],
+        other: &mut Circuit,
+        other_inputs: &[InputId],
+        other_outputs: &[NodeId],
+        max_inputs: usize,
+    ) -> Result<Option<u64>, TooManyInputs> {
+        assert!(
+            inputs.len() == other_inputs.len() && outputs.len() == other_outputs.len(),
+            "Circuits have different numbers of inputs or outputs"
+        );
+        if inputs.len() > max_inputs.min(TRUTH_TABLE_MAX_INPUTS) {
+            return Err(TooManyInputs(inputs.len()));
+        }
+        let table = self.truth_table(inputs, outputs);
+        let other_table = other.truth_table(other_inputs, other_outputs);
+        Ok(table
+            .into_iter()
+            .zip(other_table)
+            .find(|(row, other_row)| row != other_row)
+            .map(|((inputs, _), _)| inputs))
+    }
+
+    /// The combinations of the [Self::primary_inputs] under which the value
+    /// of `node` doesn't reach any of the `outputs`, packed like in
+    /// [Self::truth_table] with inputs in the order `primary_inputs` gives,
+    /// leaving out `node` itself. Found by cutting `node` off from its
+    /// inputs in two copies of the circuit and forcing it low in one and
+    /// high in the other, so the combinations are those where both copies
+    /// agree. Logic driving only these could be simplified freely.
+    pub fn observability_dont_cares(
+        &self,
+        node: NodeId,
+        outputs: &[NodeId],
+    ) -> Result<Vec<u64>, TooManyInputs> {
+        let inputs: Vec<_> = self
+            .primary_inputs()
+            .into_iter()
+            .filter(|input| *input != node)
+            .collect();
+        if inputs.len() > TRUTH_TABLE_MAX_INPUTS {
+            return Err(TooManyInputs(inputs.len()));
+        }
+        let parents: Vec<_> = self
+            .edges()
+            .filter(|(_, output)| *output == node)
+            .map(|(input, _)| input)
+            .collect();
+        let [low, high] = [false, true].map(|val| {
+            let mut forced = self.clone();
+            for parent in parents.iter().cloned() {
+                forced.disconnect(parent, node);
+            }
+            // Settles the disconnects first, so they can't override the
+            // forced value
+            forced.run_until_done();
+            forced.set_input(node, val);
+            forced.truth_table(&inputs, outputs)
+        });
+        Ok(low
+            .into_iter()
+            .zip(high)
+            .filter(|(low_row, high_row)| low_row == high_row)
+            .map(|((inputs, _), _)| inputs)
+            .collect())
+    }
+
+    /// Pulses `clock` for `cycles` cycles, recording the `outputs` once the
+    /// circuit settles after each rising edge, one row per cycle.
+    pub fn sample_cycles(
+        &mut self,
+        clock: InputId,
+        cycles: usize,
+        outputs: &[NodeId],
+    ) -> Vec<Vec<bool>> {
+        (0..cycles)
+            .map(|_| {
+                self.set_input(clock, false);
+                self.run_until_done();
+                self.apply(&[(clock, true)], outputs)
+            })
+            .collect()
+    }
+
+    /// Runs the circuit through `steps`, one clock cycle each. Each step
+    /// lowers `clock`, applies its inputs, then raises `clock` and checks
+    /// its outputs, panicking with every mismatched output on failure.
+    pub fn assert_trace(&mut self, clock: InputId, steps: &[TraceStep]) {
+        for (i, step) in steps.iter().enumerate() {
+            self.set_input(clock, false);
+            for (input, val) in step.inputs.iter().cloned() {
+                self.set_input(input, val);
+            }
+            self.run_until_done();
+            self.set_input(clock, true);
+            self.run_until_done();
+
+            let mismatches: Vec<_> = step
+                .outputs
+                .iter()
+                .filter(|(output, val)| self.get_output(*output) != *val)
+                .map(|(output, val)| format!("  {output:?}: expected {val}, got {}", !val))
+                .collect();
+            assert!(
+                mismatches.is_empty(),
+                "Trace mismatch at step {i} (tick {}):\n{}",
+                self.tick(),
+                mismatches.join("\n")
+            );
+        }
+    }
+
+    /// Checks a state machine against a software model for `cycles` clock
+    /// cycles. Each cycle lowers `clock`, sets `inputs` to random values and
+    /// raises `clock`, then compares the state decoded by `read_state` with
+    /// what `reference` computes from the previous state and the input
+    /// values. Panics with the first cycle where they diverge. The inputs
+    /// are drawn from `seed`, so a failing run can be repeated.
+    pub fn check_fsm<S, R, F>(
+        &mut self,
+        clock: InputId,
+        inputs: &[InputId],
+        read_state: R,
+        mut reference: F,
+        cycles: usize,
+        seed: u64,
+    ) where
+        S: PartialEq + Debug,
+        R: Fn(&Self) -> S,
+        F: FnMut(S, &[bool]) -> S,
+    {
+        let mut rng = StdRng::seed_from_u64(seed);
+        self.set_input(clock, false);
+        self.run_until_done();
+        let mut state = read_state(self);
+        for cycle in 0..cycles {
+            self.set_input(clock, false);
+            let vals: Vec<_> = inputs.iter().map(|_| rng.gen()).collect();
+            for (input, val) in inputs.iter().cloned().zip(vals.iter().cloned()) {
+                self.set_input(input, val);
+            }
+            self.run_until_done();
+            self.set_input(clock, true);
+            self.run_until_done();
+
+            let expected = reference(state, &vals);
+            state = read_state(self);
+            assert!(
+                state == expected,
+                "FSM diverged at cycle {cycle} with inputs {vals:?}: expected {expected:?}, got {state:?}"
+            );
+        }
+    }
+
+    /// Nodes without any inputs, which are driven by [CircuitSim::set_input].
+    /// [NodeType::Const](crate::circuit_sim::NodeType::Const) nodes are not
+    /// included.
+    pub fn primary_inputs(&self) -> Vec<InputId> {
+        let has_parent: HashSet<_> = self.edges().map(|(_, output)| output).collect();
+        self.node_ids()
+            .filter(|node_id| !has_parent.contains(node_id) && !self.is_const(*node_id))
+            .collect()
+    }
+
+    /// Drives the primary inputs with `test_vectors` random values and
+    /// reports the nodes whose output never changed, along with that output.
+    /// This is statistical, so a node reported here could still change under
+    /// inputs that weren't tried. Inputs are restored afterwards.
+    pub fn find_constant_nodes(&mut self, test_vectors: usize, seed: u64) -> Vec<(NodeId, bool)> {
+        let inputs = self.primary_inputs();
+        let original: Vec<_> = inputs.iter().map(|input| self.get_output(*input)).collect();
+        self.run_until_done();
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        // Indexed by position in `node_ids`, which skips deleted ids
+        let node_ids: Vec<_> = self.node_ids().collect();
+        let initial: Vec<_> = node_ids
+            .iter()
+            .map(|node_id| self.get_output(*node_id))
+            .collect();
+        let mut changed = vec![false; node_ids.len()];
+        for _ in 0..test_vectors {
+            for input in inputs.iter().cloned() {
+                self.set_input(input, rng.gen());
+            }
+            self.run_until_done();
+            for (i, node_id) in node_ids.iter().enumerate() {
+                changed[i] |= self.get_output(*node_id) != initial[i];
+            }
+        }
+
+        for (input, val) in inputs.iter().cloned().zip(original) {
+            self.set_input(input, val);
+        }
+        self.run_until_done();
+
+        let inputs: HashSet<_> = inputs.into_iter().collect();
+        node_ids
+            .into_iter()
+            .zip(initial)
+            .zip(changed)
+            .filter(|((node_id, _), changed)| !changed && !inputs.contains(node_id))
+            .map(|(constant, _)| constant)
+            .collect()
+    }
+
+    /// Drives the primary inputs with `test_vectors` random values and
+    /// reports the pairs of `wires`, by index with the lower first, that
+    /// always carried the same values. Such wires could share one set of
+    /// nodes. Like [Self::find_constant_nodes] this is statistical, and
+    /// inputs are restored afterwards. Wires of different widths never
+    /// match.
+    pub fn find_equivalent_wires(
+        &mut self,
+        wires: &[&[NodeId]],
+        test_vectors: usize,
+        seed: u64,
+    ) -> Vec<(usize, usize)> {
+        let inputs = self.primary_inputs();
+        let original: Vec<_> = inputs.iter().map(|input| self.get_output(*input)).collect();
+        self.run_until_done();
+
+        let read = |circuit: &Self| -> Vec<Vec<bool>> {
+            wires
+                .iter()
+                .map(|wire| {
+                    wire.iter()
+                        .map(|node_id| circuit.get_output(*node_id))
+                        .collect()
+                })
+                .collect()
+        };
+        let initial = read(self);
+        let mut pairs: Vec<_> = (0..wires.len())
+            .flat_map(|i| (i + 1..wires.len()).map(move |j| (i, j)))
+            .filter(|(i, j)| initial[*i] == initial[*j])
+            .collect();
+        let mut rng = StdRng::seed_from_u64(seed);
+        for _ in 0..test_vectors {
+            if pairs.is_empty() {
+                break;
+            }
+            for input in inputs.iter().cloned() {
+                self.set_input(input, rng.gen());
+            }
+            self.run_until_done();
+            let values = read(self);
+            pairs.retain(|(i, j)| values[*i] == values[*j]);
+        }
+
+        for (input, val) in inputs.iter().cloned().zip(original) {
+            self.set_input(input, val);
+        }
+        self.run_until_done();
+        pairs
+    }
+
+    /// Applies the same `inputs` to both circuits and steps them together,
+    /// returning the first tick at which any node differs between them along
+    /// with the differing nodes. Nodes are matched by [NodeId], so this is
+    /// meant for circuits built the same way; nodes only one of them has are
+    /// ignored.
+    pub fn lockstep(
+        &mut self,
+        other: &mut Self,
+        inputs: &[(InputId, bool)],
+    ) -> Option<(Tick, Vec<NodeId>)> {
+        for (input, val) in inputs.iter().cloned() {
+            self.set_input(input, val);
+            other.set_input(input, val);
+        }
+        let other_ids: HashSet<_> = other.node_ids().collect();
+        let shared: Vec<_> = self
+            .node_ids()
+            .filter(|node_id| other_ids.contains(node_id))
+            .collect();
+        loop {
+            let tick = self.tick();
+            let diverged: Vec<_> = shared
+                .iter()
+                .cloned()
+                .filter(|node_id| self.get_output(*node_id) != other.get_output(*node_id))
+                .collect();
+            if !diverged.is_empty() {
+                return Some((tick, diverged));
+            }
+            if !self.work_left() && !other.work_left() {
+                return None;
+            }
+            self.update();
+            other.update();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, sync::Arc};
+
+    use crate::{
+        circuit_builder::{ops::*, CircuitBuilder, Connector},
+        circuit_sim::{CircuitSim, NodeType},
+        components::{memory::create_d_latch, wire::Wire},
+        Circuit, InputId, NodeId,
+    };
+
+    use super::{TooManyInputs, TraceStep};
+
+    struct FullAdder {
+        circuit: Circuit,
+        inputs: [NodeId; 3],
+        sum: NodeId,
+        carry: NodeId,
+    }
+
+    fn full_adder(broken: bool) -> FullAdder {
+        let mut circuit = Circuit::new();
+        let inputs = [(); 3].map(|_| circuit.create_input());
+        let sum = circuit.create_node(NodeType::Xor);
+        let carry = circuit.create_node(NodeType::Or);
+        for (i, input) in inputs.iter().cloned().enumerate() {
+            circuit.connect(input, sum);
+            let node_type = if broken && i == 0 {
+                NodeType::Or
+            } else {
+                NodeType::And
+            };
+            let pair = circuit.create_node(node_type);
+            circuit.connect(input, pair);
+            circuit.connect(inputs[(i + 1) % 3], pair);
+            circuit.connect(pair, carry);
+        }
+        circuit.run_until_done();
+        FullAdder {
+            circuit,
+            inputs,
+            sum,
+            carry,
+        }
+    }
+
+    /// Rising edge triggered D flip-flop built from two latches, returning
+    /// the circuit along with the data, clock and output nodes.
+    fn d_flip_flop() -> (Arc<RefCell<CircuitBuilder>>, InputId, InputId, NodeId) {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let (d, d_id) = Connector::input(builder.clone());
+        let (clock, clock_id) = Connector::input(builder.clone());
+        let master = create_d_latch(d, clock.invert());
+        let q = create_d_latch(master, clock).output;
+        builder.borrow_mut().circuit.run_until_done();
+        (builder, d_id, clock_id, q)
+    }
+
+    #[test]
+    fn sample_cycles_test() {
+        // 4 bit counter out of flip-flops feeding an incrementer
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let (clock, clock_id) = Connector::input(builder.clone());
+        let clock_not = clock.invert();
+        let (d, q): (Vec<_>, Vec<_>) = (0..4)
+            .map(|_| {
+                let d = Connector::new(builder.clone());
+                let d_id = d.output;
+                let master = create_d_latch(d, or!(clock_not));
+                let q = create_d_latch(master, or!(clock)).output;
+                (d_id, q)
+            })
+            .unzip();
+        let circuit = &mut builder.borrow_mut().circuit;
+        let count = Wire::<4>::of_node_ids(|bit| q[bit]);
+        let (next, _) = count.increment(circuit);
+        next.connect(circuit, &Wire::of_node_ids(|bit| d[bit]));
+        circuit.run_until_done();
+
+        let rows = circuit.sample_cycles(clock_id, 20, &q);
+        assert_eq!(rows.len(), 20);
+        for (cycle, row) in rows.iter().enumerate() {
+            let val = row
+                .iter()
+                .enumerate()
+                .fold(0, |val, (bit, set)| val | (*set as usize) << bit);
+            assert_eq!(val, (cycle + 1) % 16, "cycle {cycle}");
+        }
+    }
+
+    #[test]
+    fn trace_test() {
+        let (builder, d, clock, q) = d_flip_flop();
+        let circuit = &mut builder.borrow_mut().circuit;
+        circuit.assert_trace(
+            clock,
+            &[
+                TraceStep {
+                    inputs: &[(d, true)],
+                    outputs: &[(q, true)],
+                },
+                TraceStep {
+                    inputs: &[],
+                    outputs: &[(q, true)],
+                },
+                TraceStep {
+                    inputs: &[(d, false)],
+                    outputs: &[(q, false)],
+                },
+                TraceStep {
+                    inputs: &[(d, true)],
+                    outputs: &[(q, true)],
+                },
+            ],
+        );
+
+        // Changing the data while the clock is high doesn't get latched
+        circuit.set_input(d, false);
+        circuit.run_until_done();
+        assert!(circuit.get_output(q));
+    }
+
+    #[test]
+    #[should_panic(expected = "Trace mismatch at step 1")]
+    fn trace_mismatch_test() {
+        let (builder, d, clock, q) = d_flip_flop();
+        let circuit = &mut builder.borrow_mut().circuit;
+        circuit.assert_trace(
+            clock,
+            &[
+                TraceStep {
+                    inputs: &[(d, true)],
+                    outputs: &[(q, true)],
+                },
+                TraceStep {
+                    inputs: &[(d, false)],
+                    outputs: &[(q, true)],
+                },
+            ],
+        );
+    }
+
+    /// 2 bit counter that counts up on rising edges while enabled, returning
+    /// the circuit along with the clock, enable and count nodes.
+    fn counter_fsm() -> (Arc<RefCell<CircuitBuilder>>, InputId, InputId, Wire<2>) {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let (clock, clock_id) = Connector::input(builder.clone());
+        let (_, enable) = Connector::input(builder.clone());
+        let clock_not = clock.invert();
+        let (d, q): (Vec<_>, Vec<_>) = (0..2)
+            .map(|_| {
+                let d = Connector::new(builder.clone());
+                let d_id = d.output;
+                let master = create_d_latch(d, or!(clock_not));
+                let q = create_d_latch(master, or!(clock)).output;
+                (d_id, q)
+            })
+            .unzip();
+        {
+            let circuit = &mut builder.borrow_mut().circuit;
+            let low = circuit.create_node(NodeType::Xor);
+            circuit.connect(q[0], low);
+            circuit.connect(enable, low);
+            circuit.connect(low, d[0]);
+            let carry = circuit.create_node(NodeType::And);
+            circuit.connect(q[0], carry);
+            circuit.connect(enable, carry);
+            let high = circuit.create_node(NodeType::Xor);
+            circuit.connect(q[1], high);
+            circuit.connect(carry, high);
+            circuit.connect(high, d[1]);
+            circuit.run_until_done();
+        }
+        (builder, clock_id, enable, Wire::of_node_ids(|bit| q[bit]))
+    }
+
+    #[test]
+    fn check_fsm_test() {
+        let (builder, clock, enable, count) = counter_fsm();
+        let circuit = &mut builder.borrow_mut().circuit;
+        for seed in 0..3 {
+            circuit.check_fsm(
+                clock,
+                &[enable],
+                |circuit| count.read::<u8>(circuit),
+                |count, inputs| (count + inputs[0] as u8) % 4,
+                100,
+                seed,
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "FSM diverged at cycle")]
+    fn check_fsm_mismatch_test() {
+        let (builder, clock, enable, count) = counter_fsm();
+        let circuit = &mut builder.borrow_mut().circuit;
+        // Wraps around to 1 instead of 0
+        circuit.check_fsm(
+            clock,
+            &[enable],
+            |circuit| count.read::<u8>(circuit),
+            |count, inputs| match count + inputs[0] as u8 {
+                4 => 1,
+                next => next,
+            },
+            100,
+            7,
+        );
+    }
+
+    #[test]
+    fn output_function_test() {
+        let mut circuit = Circuit::new();
+        let inputs: Vec<_> = (0..7).map(|_| circuit.create_input()).collect();
+        let xor = circuit.create_node(NodeType::Xor);
+        circuit.connect(inputs[0], xor);
+        circuit.connect(inputs[1], xor);
+        let and = circuit.create_node(NodeType::And);
+        for input in inputs.iter().cloned() {
+            circuit.connect(input, and);
+        }
+        circuit.set_input(inputs[1], true);
+        circuit.run_until_done();
+
+        assert_eq!(circuit.output_function(xor, &inputs[..2]), Ok(0b0110));
+        assert_eq!(circuit.output_function(xor, &inputs[1..2]), Ok(0b10));
+        assert!(circuit.get_output(inputs[1]));
+        assert!(circuit.get_output(xor));
+        assert_eq!(circuit.output_function(and, &inputs), Err(TooManyInputs(7)));
+    }
+
+    #[test]
+    fn truth_table_test() {
+        let mut adder = full_adder(false);
+        let table = adder
+            .circuit
+            .truth_table(&adder.inputs, &[adder.sum, adder.carry]);
+        let expected: Vec<_> = (0..8)
+            .map(|row: u64| (row, row.count_ones() as u64))
+            .collect();
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn equivalent_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let (a, a_id) = Connector::input(builder.clone());
+        let (b, b_id) = Connector::input(builder.clone());
+        let xor_id = xor!(a, b).output;
+        let mut borrow = builder.borrow_mut();
+        let (builder_circuit, _) = borrow.build();
+        builder_circuit.run_until_done();
+
+        // (a | b) & !(a & b), and a broken one that leaves out the NAND
+        let hand_built = |broken: bool| {
+            let mut circuit = Circuit::new();
+            let inputs = [(); 2].map(|_| circuit.create_input());
+            let or = circuit.create_node(NodeType::Or);
+            let nand = circuit.create_node(NodeType::Nand);
+            let xor = circuit.create_node(NodeType::And);
+            for input in inputs {
+                circuit.connect(input, or);
+                circuit.connect(input, nand);
+            }
+            circuit.connect(or, xor);
+            if !broken {
+                circuit.connect(nand, xor);
+            }
+            circuit.run_until_done();
+            (circuit, inputs, xor)
+        };
+
+        for (broken, expected) in [(false, None), (true, Some(0b11))] {
+            let (mut circuit, inputs, xor) = hand_built(broken);
+            assert_eq!(
+                builder_circuit.equivalent(
+                    &[a_id, b_id],
+                    &[xor_id],
+                    &mut circuit,
+                    &inputs,
+                    &[xor],
+                    8
+                ),
+                Ok(expected),
+                "broken: {broken}"
+            );
+        }
+        let (mut circuit, inputs, xor) = hand_built(false);
+        assert_eq!(
+            builder_circuit.equivalent(&[a_id, b_id], &[xor_id], &mut circuit, &inputs, &[xor], 1),
+            Err(TooManyInputs(2))
+        );
+    }
+
+    #[test]
+    fn observability_dont_cares_test() {
+        let mut circuit = Circuit::new();
+        let inputs = [(); 3].map(|_| circuit.create_input());
+        let node = circuit.create_node(NodeType::Or);
+        circuit.connect(inputs[0], node);
+        circuit.connect(inputs[1], node);
+        // Blocked by a constant low, so never observable
+        let zero = circuit.constant(false);
+        let blocked = circuit.create_node(NodeType::And);
+        circuit.connect(node, blocked);
+        circuit.connect(zero, blocked);
+        // Only observable while the third input is high
+        let gated = circuit.create_node(NodeType::And);
+        circuit.connect(node, gated);
+        circuit.connect(inputs[2], gated);
+        circuit.set_input(inputs[0], true);
+        circuit.run_until_done();
+
+        assert_eq!(
+            circuit.observability_dont_cares(node, &[blocked]),
+            Ok((0..8).collect())
+        );
+        assert_eq!(
+            circuit.observability_dont_cares(node, &[blocked, gated]),
+            Ok((0..4).collect())
+        );
+        assert_eq!(
+            circuit.observability_dont_cares(node, &[node]),
+            Ok(Vec::new())
+        );
+    }
+
+    #[test]
+    fn counterexample_test() {
+        let mut golden = full_adder(false);
+        let mut same = full_adder(false);
+        let mut broken = full_adder(true);
+        let outputs = [golden.sum, golden.carry];
+        let inputs = golden.inputs;
+        assert_eq!(
+            golden
+                .circuit
+                .find_counterexample(&mut same.circuit, &inputs, &outputs),
+            None
+        );
+        // The broken carry ORs the first two inputs, which only matters when
+        // exactly one of them is set
+        assert_eq!(
+            golden
+                .circuit
+                .find_counterexample(&mut broken.circuit, &inputs, &outputs),
+            Some(vec![true, false, false])
+        );
+    }
+
+    #[test]
+    fn constant_nodes_test() {
+        let mut circuit = Circuit::new();
+        let a = circuit.create_input();
+        let b = circuit.create_input();
+        let not_a = circuit.create_node(NodeType::Nor);
+        circuit.connect(a, not_a);
+        let tautology = circuit.create_node(NodeType::Or);
+        circuit.connect(a, tautology);
+        circuit.connect(not_a, tautology);
+        let contradiction = circuit.create_node(NodeType::And);
+        circuit.connect(a, contradiction);
+        circuit.connect(not_a, contradiction);
+        let and = circuit.create_node(NodeType::And);
+        circuit.connect(a, and);
+        circuit.connect(b, and);
+        let one = circuit.create_node(NodeType::Const(true));
+        circuit.set_input(b, true);
+        circuit.run_until_done();
+
+        let mut constants = circuit.find_constant_nodes(32, 0);
+        constants.sort();
+        assert_eq!(
+            constants,
+            vec![(tautology, true), (contradiction, false), (one, true)]
+        );
+        assert!(circuit.get_output(b));
+        assert!(!circuit.get_output(a));
+    }
+
+    #[test]
+    fn equivalent_wires_test() {
+        let mut circuit = Circuit::new();
+        let source = Wire::<4>::new(&mut circuit);
+        let other = Wire::<4>::new(&mut circuit);
+        let copy = source.buffer(&mut circuit);
+        let double_inverted = source.invert(&mut circuit).invert(&mut circuit);
+        let inverted = source.invert(&mut circuit);
+        circuit.run_until_done();
+
+        let wires: Vec<Vec<NodeId>> = [source, other, copy, double_inverted, inverted]
+            .iter()
+            .map(|wire| wire.iter().cloned().collect())
+            .collect();
+        let wire_refs: Vec<_> = wires.iter().map(Vec::as_slice).collect();
+        assert_eq!(
+            circuit.find_equivalent_wires(&wire_refs, 32, 0),
+            vec![(0, 2), (0, 3), (2, 3)]
+        );
+    }
+
+    #[test]
+    fn lockstep_test() {
+        let mut golden = full_adder(false);
+        let mut same = full_adder(false);
+        let [a, b, cin] = golden.inputs;
+        for inputs in [[false, false, true], [true, true, false]] {
+            let inputs = [(a, inputs[0]), (b, inputs[1]), (cin, inputs[2])];
+            assert_eq!(golden.circuit.lockstep(&mut same.circuit, &inputs), None);
+        }
+        assert!(!golden.circuit.get_output(golden.sum));
+        assert!(golden.circuit.get_output(golden.carry));
+
+        let mut golden = full_adder(false);
+        let mut broken = full_adder(true);
+        let inputs = [(a, false), (b, false), (cin, true)];
+        assert_eq!(golden.circuit.lockstep(&mut broken.circuit, &inputs), None);
+
+        let start = golden.circuit.tick();
+        let inputs = [(a, true), (b, false), (cin, false)];
+        let (tick, diverged) = golden
+            .circuit
+            .lockstep(&mut broken.circuit, &inputs)
+            .unwrap();
+        // The broken gate diverges first, a tick before the carry does
+        assert_eq!(tick, start + 1);
+        assert_eq!(diverged.len(), 1);
+        assert!(!golden.circuit.get_output(diverged[0]));
+        assert!(broken.circuit.get_output(diverged[0]));
+        broken.circuit.run_until_done();
+        assert!(broken.circuit.get_output(broken.carry));
+    }
+}
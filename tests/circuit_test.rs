@@ -1,12 +1,24 @@
+// The builder API is built around [Arc<RefCell<_>>]
+#![allow(clippy::arc_with_non_send_sync)]
+
 #[cfg(test)]
 mod test {
-    use std::{cell::RefCell, collections::BTreeMap, sync::Arc};
+    use std::{
+        cell::RefCell,
+        collections::BTreeMap,
+        sync::{Arc, Mutex},
+    };
 
     use digisim::{
         circuit_builder::{
             ops::*, BuilderHooks, CircuitBuilder, CircuitBuilderWithHooks, Connector, NoHooks,
         },
         circuit_sim::*,
+        components::{
+            adder::RippleCarryAdder,
+            flipflop::RippleCounter,
+            memory::{create_d_latch, create_shift_register},
+        },
         Circuit, NodeId,
     };
 
@@ -49,7 +61,56 @@ mod test {
         let (circuit, marks) = borrow.build();
         let ticks = circuit.run(100);
         println!("{:?}", ticks);
-        marks.print(&circuit);
+        marks.print(circuit);
+    }
+
+    #[test]
+    fn shift_register_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilderWithHooks::<Marks>::default()));
+        let (input, input_id) = Connector::input(builder.clone());
+        let (clock, clock_id) = Connector::input(builder.clone());
+        let stages = create_shift_register(input, &clock, 4);
+        for (i, stage) in stages.iter().enumerate() {
+            stage.mark(format!("stage-{i}"));
+        }
+        let mut borrow = builder.borrow_mut();
+        let (circuit, marks) = borrow.build();
+        circuit.run_until_done();
+
+        for cycle in 0..4 {
+            circuit.set_input(clock_id, false);
+            circuit.set_input(input_id, cycle == 0);
+            circuit.run_until_done();
+            circuit.set_input(clock_id, true);
+            circuit.run_until_done();
+
+            println!("cycle {cycle}:");
+            marks.print(circuit);
+            for (i, stage) in stages.iter().enumerate() {
+                assert_eq!(circuit.get_output(stage.output), i == cycle, "stage {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn combinational_loops_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let rca = RippleCarryAdder::<4>::new(builder.clone(), Connector::new(builder.clone()));
+        let (d, _) = Connector::input(builder.clone());
+        let (enable, _) = Connector::input(builder.clone());
+        create_d_latch(d, enable);
+        assert!(builder.borrow().find_combinational_loops().is_empty());
+
+        let ring = [(); 3].map(|_| Connector::new(builder.clone()).invert());
+        for i in 0..ring.len() {
+            ring[i].connect(&ring[(i + 1) % ring.len()]);
+        }
+        let loops = builder.borrow().find_combinational_loops();
+        assert_eq!(loops.len(), 1);
+        for inverter in ring.iter() {
+            assert!(loops[0].contains(&inverter.output));
+        }
+        assert!(!loops[0].contains(&rca.cout));
     }
 
     fn gate_test_gen(
@@ -65,7 +126,7 @@ mod test {
         let (circuit, _) = borrow.build();
         let expecteds = [(false, false), (false, true), (true, false), (true, true)]
             .into_iter()
-            .zip(expecteds.into_iter());
+            .zip(expecteds);
         for ((in_a, in_b), expected) in expecteds {
             circuit.set_input(input_a, in_a);
             circuit.set_input(input_b, in_b);
@@ -84,4 +145,803 @@ mod test {
         gate_test_gen("xor", xor, [false, true, true, false]);
         gate_test_gen("xnor", xnor, [true, false, false, true]);
     }
+
+    #[test]
+    fn buffer_not_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let buffer = circuit.create_node(NodeType::Buffer);
+        let not = circuit.create_node(NodeType::Not);
+        circuit.connect(input, buffer);
+        circuit.connect(input, not);
+        for val in [false, true, false] {
+            let outputs = circuit.apply(&[(input, val)], &[buffer, not]);
+            assert_eq!(outputs, vec![val, !val], "input {val}");
+        }
+        assert_eq!(circuit.node_kind(buffer), NodeType::Or);
+        assert_eq!(circuit.node_kind(not), NodeType::Nor);
+    }
+
+    #[test]
+    fn hold_input_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let not = circuit.create_node(NodeType::Not);
+        circuit.connect(input, not);
+        circuit.run_until_done();
+
+        circuit.hold_input(input, true, 3);
+        for tick in 0..3 {
+            circuit.update();
+            assert!(circuit.get_output(input), "tick {tick}");
+        }
+        assert!(!circuit.get_output(not));
+        assert!(circuit.work_left());
+        circuit.update();
+        assert!(!circuit.get_output(input));
+        circuit.run_until_done();
+        assert!(circuit.get_output(not));
+    }
+
+    #[test]
+    fn clock_test() {
+        let mut circuit = Circuit::new();
+        let clock = circuit.clock(5);
+        let buffer = circuit.create_node(NodeType::Buffer);
+        circuit.connect(clock, buffer);
+        assert!(!circuit.work_left());
+
+        let mut edges = 0;
+        let mut last = circuit.get_output(buffer);
+        for _ in 0..100 {
+            circuit.update();
+            if circuit.get_output(buffer) != last {
+                last = !last;
+                edges += 1;
+            }
+        }
+        // Toggles on ticks 5, 10, ..., 95
+        assert_eq!(edges, 19);
+    }
+
+    #[test]
+    fn memory_bytes_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let empty = circuit.memory_bytes();
+        for _ in 0..1000 {
+            let node_id = circuit.create_node(NodeType::Nor);
+            circuit.connect(input, node_id);
+        }
+        let bytes = circuit.memory_bytes();
+        // At least a few bytes per node and edge, but not a page each
+        assert!(bytes > empty + 1000 * 8, "{bytes} bytes");
+        assert!(bytes < 1000 * 256, "{bytes} bytes");
+
+        circuit.create_nodes(NodeType::Or, 10_000);
+        assert!(circuit.memory_bytes() > bytes);
+    }
+
+    #[test]
+    fn snapshot_test() {
+        let mut circuit = Circuit::new();
+        let set = circuit.create_input();
+        let reset = circuit.create_input();
+        let q = circuit.create_node(NodeType::Nor);
+        let q_not = circuit.create_node(NodeType::Nor);
+        circuit.connect(reset, q);
+        circuit.connect(q_not, q);
+        circuit.connect(set, q_not);
+        circuit.connect(q, q_not);
+        circuit.apply(&[(set, true)], &[]);
+        circuit.apply(&[(set, false)], &[]);
+        assert!(circuit.get_output(q));
+
+        let holding = circuit.snapshot();
+        circuit.apply(&[(reset, true)], &[]);
+        circuit.apply(&[(reset, false)], &[]);
+        assert!(!circuit.get_output(q));
+        circuit.restore(&holding);
+        assert!(circuit.get_output(q));
+        assert!(!circuit.work_left());
+
+        // Restoring in the middle of propagating carries on the same way
+        circuit.set_input(reset, true);
+        circuit.update();
+        let propagating = circuit.snapshot();
+        let tick = circuit.tick();
+        circuit.run_until_done();
+        let ticks = circuit.tick() - tick;
+        circuit.restore(&propagating);
+        assert_eq!(circuit.tick(), tick);
+        circuit.run_until_done();
+        assert_eq!(circuit.tick() - tick, ticks);
+        assert!(!circuit.get_output(q));
+        assert!(circuit.get_output(reset));
+    }
+
+    #[test]
+    fn clone_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let (cin, cin_id) = Connector::input(builder.clone());
+        let rca = RippleCarryAdder::<8>::new(builder.clone(), cin);
+        let mut borrow = builder.borrow_mut();
+        let (circuit, _) = borrow.build();
+        circuit.run_until_done();
+        for input in rca.input_a.iter().chain([&cin_id]) {
+            circuit.set_input(*input, true);
+        }
+        circuit.update();
+        circuit.update();
+
+        let mut clone = circuit.clone();
+        assert_eq!(clone.tick(), circuit.tick());
+        assert_eq!(clone.remaining_ids(), circuit.remaining_ids());
+        while circuit.work_left() {
+            assert!(clone.work_left());
+            circuit.update();
+            clone.update();
+            assert!(circuit
+                .node_ids()
+                .all(|node_id| { circuit.get_output(node_id) == clone.get_output(node_id) }));
+        }
+        assert!(!clone.work_left());
+
+        // Diverging from here on doesn't affect the original
+        clone.apply(&[(cin_id, false)], &[]);
+        assert!(circuit.get_output(rca.cout));
+        assert!(!clone.get_output(rca.cout));
+    }
+
+    #[test]
+    fn probe_test() {
+        let mut circuit = Circuit::new();
+        let counter = RippleCounter::<2>::new(&mut circuit);
+        circuit.run_until_done();
+
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = changes.clone();
+        circuit.set_probe(
+            counter.bits[1],
+            Box::new(move |tick, val| recorded.lock().unwrap().push((tick, val))),
+        );
+        // Counts once, returning the ticks it took to settle and the count
+        let count = |circuit: &mut Circuit| {
+            let start = circuit.tick();
+            for clk in [true, false] {
+                circuit.set_input(counter.clk, clk);
+                circuit.run_until_done();
+            }
+            (start..=circuit.tick(), counter.read::<u8>(circuit))
+        };
+        let counts: Vec<_> = (0..4).map(|_| count(&mut circuit)).collect();
+        let values: Vec<_> = counts.iter().map(|(_, count)| *count).collect();
+        assert_eq!(values, [1, 2, 3, 0]);
+
+        // The high bit goes up counting to 2 and down wrapping around to 0
+        let changes_so_far = changes.lock().unwrap().clone();
+        assert_eq!(changes_so_far.len(), 2, "{changes_so_far:?}");
+        for ((tick, val), (ticks, count)) in changes_so_far.iter().zip([&counts[1], &counts[3]]) {
+            assert!(ticks.contains(tick), "{tick} not in {ticks:?}");
+            assert_eq!(*val, count & 2 != 0);
+        }
+
+        circuit.remove_probe(counter.bits[1]);
+        for _ in 0..4 {
+            count(&mut circuit);
+        }
+        assert_eq!(changes.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn remaining_ids_test() {
+        let mut circuit = Circuit::new();
+        let initial = circuit.remaining_ids();
+        assert_eq!(initial, u32::MAX as u64);
+        let node_id = circuit.create_node(NodeType::Or);
+        assert_eq!(circuit.remaining_ids(), initial - 1);
+        let input_id = circuit.create_input();
+        assert_eq!(circuit.remaining_ids(), initial - 2);
+        circuit.connect(input_id, node_id);
+        assert!(circuit.try_create_node(NodeType::And).is_ok());
+        assert_eq!(circuit.remaining_ids(), initial - 3);
+    }
+
+    #[test]
+    fn apply_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let rca = RippleCarryAdder::<4>::new(builder.clone(), Connector::new(builder.clone()));
+        let mut borrow = builder.borrow_mut();
+        let (circuit, _) = borrow.build();
+        let (a, b) = (0b0110, 0b0011);
+        let inputs: Vec<_> = (0..4)
+            .flat_map(|i| {
+                [
+                    (rca.input_a[i], a & (1 << i) != 0),
+                    (rca.input_b[i], b & (1 << i) != 0),
+                ]
+            })
+            .collect();
+        let sum = circuit
+            .apply(&inputs, &rca.sum)
+            .into_iter()
+            .enumerate()
+            .fold(0, |sum, (i, bit)| sum | ((bit as u32) << i));
+        assert_eq!(sum, a + b);
+    }
+
+    #[test]
+    fn debug_summary_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        for _ in 0..999 {
+            let node_id = circuit.create_node(NodeType::Nor);
+            circuit.connect(input, node_id);
+        }
+        let summary = format!("{circuit:?}");
+        assert!(summary.len() < 100, "{summary}");
+        assert!(summary.contains("nodes: 1000"), "{summary}");
+        assert!(summary.contains("edges: 999"), "{summary}");
+
+        let detailed = format!("{circuit:#?}");
+        assert!(detailed.len() > summary.len());
+        assert!(detailed.len() < 100_000);
+        assert!(detailed.ends_with("..\n}"), "{detailed}");
+    }
+
+    #[test]
+    fn edges_test() {
+        let builder = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let (a, _) = Connector::input(builder.clone());
+        let (b, _) = Connector::input(builder.clone());
+        let sum = xor!(a, b);
+        let carry = and!(a, b);
+        let mut borrow = builder.borrow_mut();
+        let (circuit, _) = borrow.build();
+        let mut edges: Vec<_> = circuit.edges().collect();
+        edges.sort();
+        let mut expected = vec![
+            (a.output, sum.output),
+            (b.output, sum.output),
+            (a.output, carry.output),
+            (b.output, carry.output),
+        ];
+        expected.sort();
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn const_test() {
+        let mut circuit = Circuit::new();
+        let one = circuit.create_node(NodeType::Const(true));
+        let zero = circuit.create_node(NodeType::Const(false));
+        let input = circuit.create_input();
+        let and = circuit.create_node(NodeType::And);
+        circuit.connect(one, and);
+        circuit.connect(input, and);
+        let or = circuit.create_node(NodeType::Or);
+        circuit.connect(zero, or);
+        circuit.connect(input, or);
+        for val in [false, true, false] {
+            assert_eq!(circuit.apply(&[(input, val)], &[and, or]), vec![val, val]);
+        }
+        assert!(circuit.get_output(one));
+        assert!(!circuit.get_output(zero));
+    }
+
+    #[test]
+    fn constant_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        circuit.set_input(input, true);
+        let tie_high = circuit.constant(true);
+        assert!(!circuit.work_left());
+        let and = circuit.create_node(NodeType::And);
+        circuit.connect(input, and);
+        circuit.connect(tie_high, and);
+        circuit.run_until_done();
+        assert!(circuit.get_output(and));
+        for val in [false, true, false] {
+            assert_eq!(circuit.apply(&[(input, val)], &[and]), vec![val]);
+        }
+
+        let tie_low = circuit.constant(false);
+        circuit.connect(tie_low, and);
+        circuit.set_input(input, true);
+        circuit.run_until_done();
+        assert!(!circuit.get_output(and));
+    }
+
+    #[test]
+    #[should_panic(expected = "into itself")]
+    #[cfg(debug_assertions)]
+    fn self_loop_test() {
+        let mut circuit = Circuit::new();
+        let chain = circuit.create_nodes(NodeType::Not, 4);
+        // Off by one, wiring the last node into itself, which would
+        // otherwise oscillate and hang the next run
+        for i in 0..chain.len() {
+            circuit.connect(chain[i], chain[(i + 1).min(chain.len() - 1)]);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "belong to different builders")]
+    fn connect_across_builders_test() {
+        let first = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let second = Arc::new(RefCell::new(CircuitBuilder::default()));
+        let (input, _) = Connector::input(first);
+        let output = Connector::new(second);
+        input.connect(&output);
+    }
+
+    #[test]
+    fn xor_simultaneous_toggle_test() {
+        let mut circuit = Circuit::new();
+        let inputs = [(); 3].map(|_| circuit.create_input());
+        let xor = circuit.create_node(NodeType::Xor);
+        for input in inputs {
+            circuit.connect(input, xor);
+        }
+        for vals in 0..8u32 {
+            for next in 0..8u32 {
+                circuit.apply(&[0, 1, 2].map(|i| (inputs[i], vals & (1 << i) != 0)), &[]);
+                // All the inputs that differ toggle in the same tick
+                let output = circuit.apply(
+                    &[0, 1, 2].map(|i| (inputs[i], next & (1 << i) != 0)),
+                    &[xor],
+                );
+                assert_eq!(
+                    output[0],
+                    next.count_ones() % 2 == 1,
+                    "{vals:03b} -> {next:03b}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn settle_cost_test() {
+        let mut circuit = Circuit::new();
+        let wide = circuit.create_input();
+        let narrow = circuit.create_input();
+        let mut level = vec![wide];
+        for _ in 0..3 {
+            level = level
+                .iter()
+                .flat_map(|input| [*input; 4])
+                .map(|input| {
+                    let output = circuit.create_node(NodeType::Nor);
+                    circuit.connect(input, output);
+                    output
+                })
+                .collect();
+        }
+        let output = circuit.create_node(NodeType::Nor);
+        circuit.connect(narrow, output);
+        circuit.run_until_done();
+
+        let wide_cost = circuit.settle_cost(wide, true);
+        let narrow_cost = circuit.settle_cost(narrow, true);
+        assert!(wide_cost > narrow_cost, "{wide_cost} <= {narrow_cost}");
+        assert!(!circuit.work_left());
+        assert_eq!(circuit.settle_cost(narrow, true), 0);
+    }
+
+    #[test]
+    fn connect_and_order_test() {
+        // Every order of connecting two low and two high inputs
+        for order in 0..16u32 {
+            let mut circuit = Circuit::new();
+            let inputs: Vec<_> = (0..4)
+                .map(|i| {
+                    let input = circuit.create_input();
+                    circuit.set_input(input, order & (1 << i) != 0);
+                    input
+                })
+                .collect();
+            let and = circuit.create_node(NodeType::And);
+            let nand = circuit.create_node(NodeType::Nand);
+            assert!(circuit.get_output(and));
+            assert!(!circuit.get_output(nand));
+
+            let mut expected = true;
+            for input in inputs {
+                circuit.connect(input, and);
+                circuit.connect(input, nand);
+                expected &= circuit.get_output(input);
+                assert_eq!(circuit.get_output(and), expected, "order {order:04b}");
+                assert_eq!(circuit.get_output(nand), !expected, "order {order:04b}");
+            }
+            circuit.run_until_done();
+            assert_eq!(circuit.get_output(and), expected, "order {order:04b}");
+        }
+    }
+
+    #[test]
+    fn disconnect_test() {
+        let mut circuit = Circuit::new();
+        let high = circuit.create_input();
+        let low = circuit.create_input();
+        circuit.set_input(high, true);
+        let and = circuit.create_node(NodeType::And);
+        circuit.connect(high, and);
+        circuit.connect(low, and);
+        circuit.run_until_done();
+        assert!(!circuit.get_output(and));
+
+        circuit.disconnect(low, and);
+        circuit.run_until_done();
+        assert!(circuit.get_output(and));
+        assert!(!circuit.edges().any(|edge| edge == (low, and)));
+
+        // Edges that don't exist are left alone
+        circuit.disconnect(low, and);
+        circuit.disconnect(and, high);
+        assert!(!circuit.work_left());
+        assert!(circuit.get_output(and));
+
+        circuit.set_input(low, true);
+        circuit.run_until_done();
+        assert!(circuit.get_output(and));
+        circuit.set_input(high, false);
+        circuit.run_until_done();
+        assert!(!circuit.get_output(and));
+    }
+
+    #[test]
+    fn delete_node_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let output = circuit.create_node(NodeType::Or);
+        for i in 0..10_000 {
+            circuit.set_input(input, i % 2 == 0);
+            let node_type = [NodeType::Nor, NodeType::And, NodeType::Xnor][i % 3];
+            let node = circuit.create_node(node_type);
+            circuit.connect(input, node);
+            circuit.connect(node, output);
+            if i % 5 == 0 {
+                circuit.run_until_done();
+                assert_eq!(circuit.get_output(output), circuit.get_output(node));
+            } else {
+                // Delete nodes waiting to be updated as well
+                circuit.update();
+            }
+
+            circuit.delete_node(node);
+            circuit.run_until_done();
+            assert!(!circuit.get_output(output), "iteration {i}");
+            assert!(circuit.num_nodes() <= 4, "{} nodes", circuit.num_nodes());
+        }
+
+        // Recycled ids start out like fresh nodes
+        let and = circuit.create_node(NodeType::And);
+        assert!(circuit.get_output(and));
+        assert!(!circuit.work_left());
+        circuit.set_input(input, false);
+        circuit.connect(input, and);
+        circuit.connect(and, output);
+        circuit.run_until_done();
+        assert!(!circuit.get_output(and));
+        circuit.set_input(input, true);
+        circuit.run_until_done();
+        assert!(circuit.get_output(output));
+    }
+
+    #[test]
+    fn delete_last_queued_test() {
+        let mut circuit = Circuit::new();
+        let inputs = [circuit.create_input(), circuit.create_input()];
+        let nots = inputs.map(|input| {
+            let not = circuit.create_node(NodeType::Not);
+            circuit.connect(input, not);
+            not
+        });
+        circuit.run_until_done();
+        circuit.set_input(inputs[0], true);
+        circuit.set_input(inputs[1], true);
+        circuit.update();
+        // Both changed, so both are waiting to notify their children. The
+        // last one in the queue has no next node, which mustn't be taken to
+        // mean it isn't queued
+        for not in nots {
+            circuit.delete_node(not);
+        }
+        let node = circuit.create_node(NodeType::Or);
+        let and = circuit.create_node(NodeType::And);
+        circuit.connect(node, and);
+        circuit.set_input(node, true);
+        circuit.run_until_done();
+        assert!(circuit.get_output(and));
+    }
+
+    #[test]
+    fn deleted_node_ids_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let nots = circuit.create_nodes(NodeType::Not, 3);
+        let and = circuit.create_node(NodeType::And);
+        for not in nots.iter().cloned() {
+            circuit.connect(input, not);
+            circuit.connect(not, and);
+        }
+        circuit.run_until_done();
+        circuit.delete_node(nots[0]);
+        // The second one is still queued, so it lingers until an update
+        circuit.set_input(input, true);
+        circuit.update();
+        circuit.delete_node(nots[1]);
+
+        let remaining = [input, nots[2], and];
+        assert_eq!(circuit.node_ids().collect::<Vec<_>>(), remaining);
+        assert_eq!(circuit.primary_inputs(), [input]);
+        let dot = circuit.to_dot();
+        assert_eq!(dot.matches("[label=").count(), remaining.len());
+        assert_eq!(dot.matches(" -> ").count(), 2);
+
+        // Reusing an id brings it back
+        let reused = circuit.create_node(NodeType::Or);
+        circuit.run_until_done();
+        assert!(circuit.node_ids().any(|node_id| node_id == reused));
+        assert_eq!(circuit.primary_inputs().len(), 2);
+    }
+
+    #[test]
+    fn deleted_node_analyses_test() {
+        let mut circuit = Circuit::new();
+        let a = circuit.create_input();
+        let doomed = circuit.create_nodes(NodeType::Nand, 2);
+        let b = circuit.create_input();
+        let xor = circuit.create_node(NodeType::Xor);
+        let one = circuit.constant(true);
+        let and = circuit.create_node(NodeType::And);
+        for (input, output) in [(a, xor), (b, xor), (xor, and), (one, and), (a, doomed[0])] {
+            circuit.connect(input, output);
+        }
+        circuit.run_until_done();
+        // Ids below live ones, one freed and one still queued
+        circuit.delete_node(doomed[1]);
+        circuit.set_input(a, true);
+        circuit.delete_node(doomed[0]);
+        circuit.run_until_done();
+
+        assert_eq!(circuit.find_constant_nodes(16, 0), [(one, true)]);
+
+        for mut universal in [circuit.to_nand_only(), circuit.to_nor_only()] {
+            for (a_val, b_val) in [(false, false), (false, true), (true, false), (true, true)] {
+                let inputs = [(a, a_val), (b, b_val)];
+                assert_eq!(
+                    universal.apply(&inputs, &[xor, and]),
+                    circuit.apply(&inputs, &[xor, and])
+                );
+            }
+        }
+
+        let mut copy = circuit.clone();
+        assert_eq!(circuit.lockstep(&mut copy, &[(b, true)]), None);
+    }
+
+    #[test]
+    fn connect_while_propagating_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let low = circuit.create_input();
+        let and = circuit.create_node(NodeType::And);
+        let output = circuit.create_node(NodeType::Or);
+        circuit.connect(input, and);
+        circuit.connect(and, output);
+        circuit.run_until_done();
+        circuit.set_input(input, true);
+        // The AND goes high, but its children haven't seen it yet
+        circuit.update();
+        assert!(circuit.get_output(and));
+        assert!(!circuit.get_output(output));
+        circuit.connect(low, and);
+        circuit.run_until_done();
+        assert!(!circuit.get_output(and));
+        assert!(!circuit.get_output(output));
+
+        circuit.set_input(low, true);
+        circuit.run_until_done();
+        assert!(circuit.get_output(and));
+        assert!(circuit.get_output(output));
+    }
+
+    #[test]
+    fn create_nodes_test() {
+        let mut circuit = Circuit::new();
+        let inputs = [circuit.create_input(), circuit.create_input()];
+        let nodes = circuit.create_nodes(NodeType::Or, 1000);
+        assert_eq!(circuit.num_nodes(), 1002);
+        assert!(nodes.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(
+            circuit.node_ids().skip(2).collect::<Vec<_>>(),
+            nodes,
+            "ids should be contiguous"
+        );
+        for node_id in nodes.iter().cloned() {
+            for input in inputs {
+                circuit.connect(input, node_id);
+            }
+        }
+        for (a, b) in [(false, false), (true, false), (false, true), (true, true)] {
+            let outputs = circuit.apply(&[(inputs[0], a), (inputs[1], b)], &nodes);
+            assert!(outputs.iter().all(|output| *output == (a || b)));
+        }
+    }
+
+    #[test]
+    fn node_kind_test() {
+        use NodeType::*;
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        assert_eq!(circuit.node_kind(input), Or);
+        for node_type in [Or, Nor, And, Nand, Xor, Xnor, Const(false), Const(true)] {
+            let node_id = circuit.create_node(node_type);
+            assert_eq!(circuit.node_kind(node_id), node_type);
+        }
+    }
+
+    #[test]
+    fn is_stable_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let mut last = input;
+        for _ in 0..4 {
+            let node_id = circuit.create_node(NodeType::Nor);
+            circuit.connect(last, node_id);
+            last = node_id;
+        }
+        assert!(circuit.is_stable());
+        assert!(matches!(circuit.run(100), RunResult::AlreadyStable));
+
+        circuit.set_input(input, true);
+        assert!(!circuit.is_stable());
+        assert!(matches!(
+            circuit.run(100),
+            RunResult::Finished { after_ticks } if after_ticks > 0
+        ));
+        assert!(circuit.is_stable());
+    }
+
+    #[test]
+    fn reached_max_ticks_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let settled = circuit.create_node(NodeType::Not);
+        circuit.connect(input, settled);
+        let ring = circuit.create_nodes(NodeType::Not, 3);
+        for i in 0..ring.len() {
+            circuit.connect(ring[i], ring[(i + 1) % ring.len()]);
+        }
+
+        match circuit.run(100) {
+            RunResult::ReachedMaxTicks { max_ticks, pending } => {
+                assert_eq!(max_ticks, 100);
+                assert!(!pending.is_empty());
+                assert!(
+                    pending.iter().all(|node_id| ring.contains(node_id)),
+                    "{pending:?}"
+                );
+            }
+            result => panic!("Inverter ring finished with {result:?}"),
+        }
+    }
+
+    #[test]
+    fn micro_step_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let chain = circuit.create_nodes(NodeType::Not, 3);
+        circuit.connect(input, chain[0]);
+        circuit.connect(chain[0], chain[1]);
+        circuit.connect(chain[1], chain[2]);
+        circuit.run_until_done();
+        assert_eq!(circuit.micro_step(), None);
+
+        let mut updated = circuit.clone();
+        circuit.set_input(input, true);
+        updated.set_input(input, true);
+        updated.run_until_done();
+
+        let mut steps = Vec::new();
+        while let Some(node_id) = circuit.micro_step() {
+            steps.push(node_id);
+        }
+        let first_step = |node_id| steps.iter().position(|step| *step == node_id).unwrap();
+        // Setting an input already tells its children
+        assert_eq!(steps[0], chain[0]);
+        assert!(first_step(chain[0]) < first_step(chain[1]));
+        assert!(first_step(chain[1]) < first_step(chain[2]));
+
+        assert!(circuit.is_stable());
+        assert_eq!(circuit.tick(), updated.tick());
+        for node_id in chain {
+            assert_eq!(circuit.get_output(node_id), updated.get_output(node_id));
+        }
+    }
+
+    #[test]
+    fn changes_since_test() {
+        let mut circuit = Circuit::new();
+        let inputs = [(); 2].map(|_| circuit.create_input());
+        let chains = inputs.map(|input| {
+            let chain = circuit.create_nodes(NodeType::Not, 3);
+            circuit.connect(input, chain[0]);
+            circuit.connect(chain[0], chain[1]);
+            circuit.connect(chain[1], chain[2]);
+            chain
+        });
+        circuit.run_until_done();
+        circuit.track_changes(64);
+
+        let start = circuit.tick();
+        circuit.set_input(inputs[0], true);
+        circuit.run_until_done();
+        let mut expected: Vec<_> = [inputs[0]]
+            .into_iter()
+            .chain(chains[0].iter().cloned())
+            .map(|node_id| (node_id, circuit.get_output(node_id)))
+            .collect();
+        expected.sort();
+        assert_eq!(circuit.changes_since(start), expected);
+        assert_eq!(circuit.changes_since(circuit.tick()), []);
+
+        // Toggling back leaves nothing different
+        circuit.set_input(inputs[0], false);
+        circuit.run_until_done();
+        assert_eq!(circuit.changes_since(start), []);
+
+        // Once the log overflows, everything counts as changed
+        circuit.track_changes(2);
+        let start = circuit.tick();
+        circuit.set_input(inputs[1], true);
+        circuit.run_until_done();
+        assert_eq!(circuit.changes_since(start).len(), circuit.num_nodes());
+    }
+
+    #[test]
+    #[should_panic(expected = "call track_changes first")]
+    fn changes_since_untracked_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        circuit.set_input(input, true);
+        circuit.run_until_done();
+        circuit.changes_since(0);
+    }
+
+    #[test]
+    fn stable_for_test() {
+        let mut circuit = Circuit::new();
+        let input = circuit.create_input();
+        let not = circuit.create_node(NodeType::Not);
+        circuit.connect(input, not);
+        circuit.run_until_done();
+        circuit.update();
+        // Counts from when tracking started
+        circuit.track_stability(true);
+        for _ in 0..3 {
+            circuit.update();
+        }
+        assert_eq!(circuit.stable_for(not), 3);
+
+        circuit.set_input(input, true);
+        assert_eq!(circuit.stable_for(input), 0);
+        // Changes count from the tick they happen in, like probes see them
+        for ticks in 1..=5 {
+            circuit.update();
+            assert_eq!(circuit.stable_for(input), ticks);
+            assert_eq!(circuit.stable_for(not), ticks);
+        }
+
+        // Later nodes count from when they were created
+        let late = circuit.create_node(NodeType::Not);
+        circuit.update();
+        assert_eq!(circuit.stable_for(late), 1);
+
+        // Nothing is kept while tracking is off
+        let tracking = circuit.memory_bytes();
+        circuit.track_stability(false);
+        assert!(circuit.memory_bytes() < tracking);
+    }
 }